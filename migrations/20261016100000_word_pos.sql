@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE words
+ADD COLUMN pos TEXT DEFAULT NULL;
+
+-- Whether the word is excluded from reviews because of its part of speech.
+ALTER TABLE words
+ADD COLUMN excluded INT NOT NULL DEFAULT FALSE;
@@ -0,0 +1,88 @@
+-- Add migration script here
+
+-- Words are now identified by their dictionary form, reading and part of speech
+-- rather than just the dictionary form. Existing words don't have a reading yet,
+-- they keep an empty one until they get split up by a retokenize.
+CREATE TEMP TABLE words_temp (
+    id INTEGER PRIMARY KEY,
+    text TEXT NOT NULL,
+    reading TEXT NOT NULL DEFAULT '',
+    pos TEXT NOT NULL DEFAULT '',
+    excluded INT NOT NULL DEFAULT FALSE,
+    count INTEGER DEFAULT 1,
+    frequency INTEGER,
+
+    reviewed INT DEFAULT 0,
+    next_review_at TEXT,
+
+	date_added TEXT NOT NULL,
+	date_first_reviewed TEXT,
+
+    review_duration INTEGER DEFAULT 0,
+    e_factor REAL DEFAULT 0,
+    repitition INTEGER DEFAULT 0,
+
+    UNIQUE(text, reading, pos)
+);
+
+-- Copy over the old rows.
+INSERT INTO words_temp (id, text, reading, pos, excluded, count, frequency, reviewed, next_review_at, date_added, date_first_reviewed, review_duration, e_factor, repitition)
+	SELECT id, text, '', COALESCE(pos, ''), excluded, count, frequency, reviewed, next_review_at, date_added, date_first_reviewed, review_duration, e_factor, repitition
+	FROM words;
+
+-- Create a temp table for the word_sentence relationship so that we can save the relationships.
+CREATE TEMP TABLE word_sentence_temp (
+    word_id INTEGER NOT NULL,
+    sentence_id INTEGER NOT NULL,
+    PRIMARY KEY (word_id, sentence_id)
+);
+
+-- Copy over data for this table
+INSERT INTO word_sentence_temp (word_id, sentence_id)
+	SELECT word_id, sentence_id
+	FROM word_sentence;
+
+-- Now drop the old tables.
+DROP TABLE words;
+DROP TABLE word_sentence;
+
+-- Make new tables and re-insert the data.
+CREATE TABLE words (
+    id INTEGER PRIMARY KEY,
+    text TEXT NOT NULL,
+    reading TEXT NOT NULL DEFAULT '',
+    pos TEXT NOT NULL DEFAULT '',
+    excluded INT NOT NULL DEFAULT FALSE,
+    count INTEGER DEFAULT 1,
+    frequency INTEGER,
+
+    reviewed INT DEFAULT 0,
+    next_review_at TEXT,
+
+	date_added TEXT NOT NULL,
+	date_first_reviewed TEXT,
+
+    review_duration INTEGER DEFAULT 0,
+    e_factor REAL DEFAULT 0,
+    repitition INTEGER DEFAULT 0,
+
+    UNIQUE(text, reading, pos)
+);
+INSERT INTO words (id, text, reading, pos, excluded, count, frequency, reviewed, next_review_at, date_added, date_first_reviewed, review_duration, e_factor, repitition)
+	SELECT id, text, reading, pos, excluded, count, frequency, reviewed, next_review_at, date_added, date_first_reviewed, review_duration, e_factor, repitition
+	FROM words_temp;
+
+CREATE TABLE word_sentence (
+    word_id INTEGER NOT NULL REFERENCES words(id) ON DELETE CASCADE,
+    sentence_id INTEGER NOT NULL REFERENCES sentences(id) ON DELETE CASCADE,
+    PRIMARY KEY (word_id, sentence_id)
+);
+CREATE INDEX IF NOT EXISTS sentence_index ON word_sentence(sentence_id);
+CREATE INDEX IF NOT EXISTS word_index ON word_sentence(word_id);
+INSERT INTO word_sentence (word_id, sentence_id)
+	SELECT word_id, sentence_id
+	FROM word_sentence_temp;
+
+-- Finally drop the temp tables.
+DROP TABLE words_temp;
+DROP TABLE word_sentence_temp;
@@ -0,0 +1,11 @@
+-- Add migration script here
+-- The tokens that make up each sentence, in order, so that sentences can be shown with readings.
+CREATE TABLE IF NOT EXISTS sentence_tokens (
+    sentence_id INTEGER NOT NULL REFERENCES sentences(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    surface TEXT NOT NULL,
+    reading TEXT NOT NULL,
+    start_offset INTEGER NOT NULL,
+    word_id INTEGER REFERENCES words(id) ON DELETE SET NULL,
+    PRIMARY KEY (sentence_id, position)
+);
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Store the full character span of each token so we can highlight words within a sentence.
+ALTER TABLE sentence_tokens
+ADD COLUMN end_offset INTEGER NOT NULL DEFAULT 0;
+
+UPDATE sentence_tokens
+SET end_offset = start_offset + length(surface);
+
+CREATE INDEX IF NOT EXISTS sentence_tokens_word_index ON sentence_tokens(word_id);
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Which tokenizer (and version of it) produced the words for each sentence.
+-- Sentences without one were tokenized before we kept track, so are always considered stale.
+ALTER TABLE sentences
+ADD COLUMN tokenizer TEXT DEFAULT NULL;
+
+ALTER TABLE sentences
+ADD COLUMN tokenizer_version TEXT DEFAULT NULL;
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Words that are different spellings of the same word (わかる, 分かる, 解る) share a lemma group
+-- and are scheduled together.
+ALTER TABLE words
+ADD COLUMN lemma_group TEXT DEFAULT NULL;
+
+CREATE INDEX IF NOT EXISTS words_lemma_group_index ON words(lemma_group);
+
+-- The user's own spelling variants, mapping a spelling to the representative form of its group.
+CREATE TABLE IF NOT EXISTS word_variants (
+    variant TEXT PRIMARY KEY NOT NULL,
+    representative TEXT NOT NULL
+);
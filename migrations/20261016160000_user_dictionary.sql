@@ -0,0 +1,19 @@
+-- Add migration script here
+-- Forced segmentations and merges applied on top of the tokenizer, see UserDictionaryEntry.
+CREATE TABLE IF NOT EXISTS user_dictionary (
+    pattern TEXT PRIMARY KEY NOT NULL,
+    reading TEXT DEFAULT NULL,
+    pos TEXT NOT NULL DEFAULT 'noun'
+);
+
+-- Hand corrected tokens for a single sentence, used instead of the tokenizer whenever the sentence is tokenized.
+-- Anything left NULL is taken from the tokenizer if it came up with the same token, or the surface text if not.
+CREATE TABLE IF NOT EXISTS sentence_token_overrides (
+    sentence_id INTEGER NOT NULL REFERENCES sentences(id) ON DELETE CASCADE,
+    start_offset INTEGER NOT NULL,
+    end_offset INTEGER NOT NULL,
+    dictionary_form TEXT DEFAULT NULL,
+    reading TEXT DEFAULT NULL,
+    pos TEXT DEFAULT NULL,
+    PRIMARY KEY (sentence_id, start_offset)
+);
@@ -0,0 +1,133 @@
+-- Add migration script here
+-- Sentences and words now belong to a language, everything we have so far is Japanese.
+ALTER TABLE sentences
+ADD COLUMN language TEXT NOT NULL DEFAULT 'ja';
+
+CREATE INDEX IF NOT EXISTS sentences_language_index ON sentences(language);
+
+-- The same spelling can be a different word in a different language, so words need rebuilding
+-- to include the language in their UNIQUE constraint.
+CREATE TEMP TABLE words_temp (
+    id INTEGER PRIMARY KEY,
+    language TEXT NOT NULL DEFAULT 'ja',
+    text TEXT NOT NULL,
+    reading TEXT NOT NULL DEFAULT '',
+    pos TEXT NOT NULL DEFAULT '',
+    excluded INT NOT NULL DEFAULT FALSE,
+    count INTEGER DEFAULT 1,
+    frequency INTEGER,
+
+    reviewed INT DEFAULT 0,
+    next_review_at TEXT,
+
+    date_added TEXT NOT NULL,
+    date_first_reviewed TEXT,
+
+    review_duration INTEGER DEFAULT 0,
+    e_factor REAL DEFAULT 0,
+    repitition INTEGER DEFAULT 0,
+
+    lemma_group TEXT DEFAULT NULL,
+
+    UNIQUE(language, text, reading, pos)
+);
+
+-- Copy over the old rows.
+INSERT INTO words_temp (id, text, reading, pos, excluded, count, frequency, reviewed, next_review_at, date_added, date_first_reviewed, review_duration, e_factor, repitition, lemma_group)
+    SELECT id, text, reading, pos, excluded, count, frequency, reviewed, next_review_at, date_added, date_first_reviewed, review_duration, e_factor, repitition, lemma_group
+    FROM words;
+
+-- Both of these reference words, so save them before dropping it.
+CREATE TEMP TABLE word_sentence_temp (
+    word_id INTEGER NOT NULL,
+    sentence_id INTEGER NOT NULL,
+    PRIMARY KEY (word_id, sentence_id)
+);
+
+INSERT INTO word_sentence_temp (word_id, sentence_id)
+    SELECT word_id, sentence_id
+    FROM word_sentence;
+
+CREATE TEMP TABLE sentence_tokens_temp (
+    sentence_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    surface TEXT NOT NULL,
+    reading TEXT NOT NULL,
+    start_offset INTEGER NOT NULL,
+    end_offset INTEGER NOT NULL,
+    word_id INTEGER,
+    PRIMARY KEY (sentence_id, position)
+);
+
+INSERT INTO sentence_tokens_temp (sentence_id, position, surface, reading, start_offset, end_offset, word_id)
+    SELECT sentence_id, position, surface, reading, start_offset, end_offset, word_id
+    FROM sentence_tokens;
+
+-- Now drop the old tables.
+DROP TABLE sentence_tokens;
+DROP TABLE word_sentence;
+DROP TABLE words;
+
+-- Make new tables and re-insert the data.
+CREATE TABLE words (
+    id INTEGER PRIMARY KEY,
+    language TEXT NOT NULL DEFAULT 'ja',
+    text TEXT NOT NULL,
+    reading TEXT NOT NULL DEFAULT '',
+    pos TEXT NOT NULL DEFAULT '',
+    excluded INT NOT NULL DEFAULT FALSE,
+    count INTEGER DEFAULT 1,
+    frequency INTEGER,
+
+    reviewed INT DEFAULT 0,
+    next_review_at TEXT,
+
+    date_added TEXT NOT NULL,
+    date_first_reviewed TEXT,
+
+    review_duration INTEGER DEFAULT 0,
+    e_factor REAL DEFAULT 0,
+    repitition INTEGER DEFAULT 0,
+
+    lemma_group TEXT DEFAULT NULL,
+
+    UNIQUE(language, text, reading, pos)
+);
+INSERT INTO words (id, language, text, reading, pos, excluded, count, frequency, reviewed, next_review_at, date_added, date_first_reviewed, review_duration, e_factor, repitition, lemma_group)
+    SELECT id, language, text, reading, pos, excluded, count, frequency, reviewed, next_review_at, date_added, date_first_reviewed, review_duration, e_factor, repitition, lemma_group
+    FROM words_temp;
+
+CREATE INDEX IF NOT EXISTS words_lemma_group_index ON words(lemma_group);
+
+CREATE TABLE word_sentence (
+    word_id INTEGER NOT NULL REFERENCES words(id) ON DELETE CASCADE,
+    sentence_id INTEGER NOT NULL REFERENCES sentences(id) ON DELETE CASCADE,
+    PRIMARY KEY (word_id, sentence_id)
+);
+INSERT INTO word_sentence (word_id, sentence_id)
+    SELECT word_id, sentence_id
+    FROM word_sentence_temp;
+
+CREATE INDEX IF NOT EXISTS sentence_index ON word_sentence(sentence_id);
+CREATE INDEX IF NOT EXISTS word_index ON word_sentence(word_id);
+
+CREATE TABLE sentence_tokens (
+    sentence_id INTEGER NOT NULL REFERENCES sentences(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    surface TEXT NOT NULL,
+    reading TEXT NOT NULL,
+    start_offset INTEGER NOT NULL,
+    end_offset INTEGER NOT NULL DEFAULT 0,
+    word_id INTEGER REFERENCES words(id) ON DELETE SET NULL,
+    PRIMARY KEY (sentence_id, position)
+);
+INSERT INTO sentence_tokens (sentence_id, position, surface, reading, start_offset, end_offset, word_id)
+    SELECT sentence_id, position, surface, reading, start_offset, end_offset, word_id
+    FROM sentence_tokens_temp;
+
+CREATE INDEX IF NOT EXISTS sentence_tokens_word_index ON sentence_tokens(word_id);
+
+-- Finally drop the temp tables.
+DROP TABLE words_temp;
+DROP TABLE word_sentence_temp;
+DROP TABLE sentence_tokens_temp;
@@ -0,0 +1,17 @@
+-- Add migration script here
+-- Each text that's added is a document, so sentences can be shown alongside the ones around them.
+CREATE TABLE IF NOT EXISTS documents (
+    id INTEGER PRIMARY KEY,
+    source TEXT NOT NULL DEFAULT "",
+    language TEXT NOT NULL,
+    date_added TEXT NOT NULL
+);
+
+-- The sentences of a document in order. The same sentence can turn up in more than one document.
+CREATE TABLE IF NOT EXISTS document_sentences (
+    document_id INTEGER NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    sentence_id INTEGER NOT NULL REFERENCES sentences(id) ON DELETE CASCADE,
+    PRIMARY KEY (document_id, position)
+);
+CREATE INDEX IF NOT EXISTS document_sentences_sentence_index ON document_sentences(sentence_id);
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- When a sentence from subtitles is said, in milliseconds from the start of the episode.
+ALTER TABLE sentences
+ADD COLUMN start_time INTEGER DEFAULT NULL;
+
+ALTER TABLE sentences
+ADD COLUMN end_time INTEGER DEFAULT NULL;
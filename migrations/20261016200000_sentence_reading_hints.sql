@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Readings that came with a sentence, like the furigana in Aozora Bunko texts, used over the tokenizer's readings.
+CREATE TABLE IF NOT EXISTS sentence_reading_hints (
+    sentence_id INTEGER NOT NULL REFERENCES sentences(id) ON DELETE CASCADE,
+    start_offset INTEGER NOT NULL,
+    end_offset INTEGER NOT NULL,
+    reading TEXT NOT NULL,
+    PRIMARY KEY (sentence_id, start_offset)
+);
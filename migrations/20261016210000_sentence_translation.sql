@@ -0,0 +1,4 @@
+-- Add migration script here
+-- A translation of the sentence, e.g. from Tatoeba, shown once the sentence has been reviewed.
+ALTER TABLE sentences
+ADD COLUMN translation TEXT DEFAULT NULL;
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Words the user already knows from before, marked as known as soon as they turn up in a sentence.
+-- An empty reading matches the word with any reading.
+CREATE TABLE IF NOT EXISTS known_words (
+    language TEXT NOT NULL,
+    text TEXT NOT NULL,
+    reading TEXT NOT NULL DEFAULT '',
+    interval_days INTEGER NOT NULL,
+    PRIMARY KEY (language, text, reading)
+);
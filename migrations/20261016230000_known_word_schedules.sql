@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Known words imported from another app (e.g. Anki) keep the schedule they had there, instead of the default for known words.
+ALTER TABLE known_words
+ADD COLUMN repitition INTEGER DEFAULT NULL;
+
+ALTER TABLE known_words
+ADD COLUMN e_factor REAL DEFAULT NULL;
+
+ALTER TABLE known_words
+ADD COLUMN review_duration INTEGER DEFAULT NULL;
+
+ALTER TABLE known_words
+ADD COLUMN next_review_at TEXT DEFAULT NULL;
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Words the user looked up while reading (e.g. on a Kindle), to learn before other new words.
+CREATE TABLE IF NOT EXISTS priority_words (
+    language TEXT NOT NULL,
+    text TEXT NOT NULL,
+    PRIMARY KEY (language, text)
+);
+
+ALTER TABLE words
+ADD COLUMN priority INT NOT NULL DEFAULT FALSE;
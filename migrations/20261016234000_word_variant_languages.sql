@@ -0,0 +1,15 @@
+-- Add migration script here
+-- Variant spellings belong to a language, so they don't group words of another language that happen to be spelt the same.
+-- Variants loaded before this were all Japanese.
+CREATE TABLE IF NOT EXISTS word_variants_by_language (
+    language TEXT NOT NULL,
+    variant TEXT NOT NULL,
+    representative TEXT NOT NULL,
+    PRIMARY KEY (language, variant)
+);
+
+INSERT INTO word_variants_by_language(language, variant, representative)
+SELECT 'ja', variant, representative FROM word_variants;
+
+DROP TABLE word_variants;
+ALTER TABLE word_variants_by_language RENAME TO word_variants;
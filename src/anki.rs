@@ -0,0 +1,245 @@
+use std::{collections::HashMap, fs::File, io::{self, Read}, path::{Path, PathBuf}, str::FromStr, sync::atomic::{AtomicUsize, Ordering}, time::SystemTime};
+
+use chrono::{Duration, Local, TimeZone};
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Connection, SqliteConnection, Row};
+use zip::ZipArchive;
+
+use crate::knowledge::{Knowledge, KnowledgeResult, KnowledgeError, KnownWord, KnownWordsReport, ReviewSchedule};
+use crate::known_words::clean_field;
+use crate::language::Language;
+
+// Anki keeps all the fields of a note in one column, separated by this.
+const FIELD_SEPARATOR: char = '\u{1f}';
+
+// Due dates below this are a number of days since the collection was made rather than a time in seconds.
+const MAX_DUE_DAY: i64 = 1_000_000;
+
+// Which field of the notes the words are in.
+#[derive(Clone, Debug)]
+pub enum NoteField {
+    // The field's name, e.g. "Expression", for whichever note types have it.
+    Name(String),
+    // The field's position counting from 1, for every note type.
+    Position(usize)
+}
+
+impl FromStr for NoteField {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().parse::<usize>() {
+            Ok(0) => Err("Fields are counted from 1".to_string()),
+            Ok(position) => Ok(Self::Position(position)),
+            Err(_) if value.trim().is_empty() => Err("The field needs a name or a position".to_string()),
+            Err(_) => Ok(Self::Name(value.trim().to_string()))
+        }
+    }
+}
+
+// The review state of a card, straight from the cards table.
+struct Card {
+    card_type: i64,
+    due: i64,
+    interval: i64,
+    factor: i64,
+    reps: i64
+}
+
+impl Card {
+    // Anki's scheduler is SM-2 underneath, so its numbers carry over with a bit of translating.
+    fn schedule(&self, created: i64) -> Option<ReviewSchedule> {
+        // The interval is in days, or negative seconds for cards that are still being learnt.
+        let duration = match self.interval {
+            interval if interval >= 0 => Duration::days(interval),
+            interval => Duration::seconds(-interval)
+        };
+        // The ease is in permille, and is 0 for cards that haven't graduated from learning yet.
+        let e_factor = match self.factor {
+            0 => 2.5,
+            factor => (factor as f64 / 1000.0).max(1.3)
+        };
+        // We go from 10 minutes to a day and only then start multiplying the interval by the ease.
+        // Cards still being learnt start that over, ones with a proper interval skip straight to multiplying it.
+        let repitition = match self.card_type {
+            2 | 3 if duration >= Duration::days(1) => self.reps.max(2),
+            _ => self.reps.min(1)
+        };
+        // Review cards are due on a day counted from when the collection was made, learning cards at a time in seconds.
+        let due = match self.due {
+            due if due < MAX_DUE_DAY => created + due * 86400,
+            due => due
+        };
+
+        Some(ReviewSchedule {
+            repitition: repitition as u32,
+            e_factor,
+            duration,
+            next_review_at: Local.timestamp_opt(due, 0).single()?.fixed_offset()
+        })
+    }
+}
+
+// How many collections this process has extracted, so each one gets its own file.
+static EXTRACTED_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// A collection taken out of an .apkg, deleted when we're done with it.
+struct ExtractedCollection(PathBuf);
+
+impl ExtractedCollection {
+    // Make a new file for it in the temp directory. The temp directory is shared with everything else,
+    // so the name has the time in it too and a file that's already there is never used.
+    fn create() -> io::Result<(Self, File)> {
+        loop {
+            let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos();
+            let name = format!("wordy_srs_anki_{}_{}_{}.anki2", std::process::id(), EXTRACTED_COUNT.fetch_add(1, Ordering::Relaxed), nanos);
+            let path = std::env::temp_dir().join(name);
+            match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((Self(path), file)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for ExtractedCollection {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// Take the collection out of an .apkg so SQLite can open it.
+// Packages made for older versions of Anki have it as collection.anki2 or collection.anki21, newer ones compress it.
+fn extract_collection(path: &Path) -> Result<ExtractedCollection, String> {
+    let mut archive = ZipArchive::new(File::open(path).map_err(|e| e.to_string())?)
+        .map_err(|e| format!("Not an .apkg file: {}", e))?;
+
+    // When both are there, collection.anki2 is only a stub telling older versions to update.
+    let name = ["collection.anki21", "collection.anki2"].into_iter()
+        .find(|name| archive.by_name(name).is_ok())
+        .ok_or_else(|| match archive.by_name("collection.anki21b").is_ok() {
+            true => "The deck was exported by a newer version of Anki, export it again with \"Support older Anki versions\" ticked".to_string(),
+            false => "There's no collection in the .apkg file".to_string()
+        })?;
+
+    let mut collection = archive.by_name(name).map_err(|e| e.to_string())?;
+    let (extracted, mut file) = ExtractedCollection::create().map_err(|e| e.to_string())?;
+    std::io::copy(&mut collection, &mut file).map_err(|e| format!("Couldn't extract the collection: {}", e))?;
+
+    Ok(extracted)
+}
+
+// Where the field is in each note type that has it, by note type id.
+async fn field_positions(connection: &mut SqliteConnection, field: &NoteField) -> KnowledgeResult<HashMap<i64, usize>> {
+    let name = match field {
+        NoteField::Name(name) => name.to_lowercase(),
+        NoteField::Position(_) => return Ok(HashMap::new())
+    };
+
+    // Newer collections have a table of fields, older ones keep the note types as JSON.
+    // Field names are compared here rather than in SQL since Anki gives them a collation only it has, and ignores case like this.
+    let rows = match sqlx::query("SELECT ntid, ord, name FROM fields").fetch_all(&mut *connection).await {
+        Ok(rows) => rows,
+        Err(_) => sqlx::query("
+            SELECT CAST(note_types.key AS INTEGER) AS ntid,
+                json_extract(fields.value, '$.ord') AS ord,
+                json_extract(fields.value, '$.name') AS name
+            FROM col, json_each(col.models) AS note_types, json_each(note_types.value, '$.flds') AS fields")
+            .fetch_all(&mut *connection).await?
+    };
+
+    let mut positions = HashMap::new();
+    for row in rows {
+        let field_name: String = row.try_get("name")?;
+        if field_name.to_lowercase() == name {
+            positions.insert(row.try_get("ntid")?, row.try_get::<i64, _>("ord")? as usize);
+        }
+    }
+
+    Ok(positions)
+}
+
+// The word in the field of each note that's been studied, with the schedule of its furthest along card.
+async fn read_words(connection: &mut SqliteConnection, field: &NoteField) -> KnowledgeResult<Vec<KnownWord>> {
+    let created: i64 = sqlx::query("SELECT crt FROM col")
+        .fetch_one(&mut *connection).await?
+        .try_get("crt")?;
+
+    let positions = field_positions(connection, field).await?;
+    if let (NoteField::Name(name), true) = (field, positions.is_empty()) {
+        return Err(KnowledgeError::InvalidImport(format!("None of the note types have a field called '{}'", name)));
+    }
+
+    // New cards haven't been studied, so there's nothing to carry over.
+    let rows = sqlx::query("
+        SELECT notes.id AS note_id, notes.mid, notes.flds, cards.type, cards.due, cards.ivl, cards.factor, cards.reps
+        FROM cards
+            INNER JOIN notes ON notes.id = cards.nid
+        WHERE cards.type != 0 AND cards.reps > 0")
+        .fetch_all(&mut *connection).await?;
+
+    // A note can have several cards (e.g. one each way), the word is as well known as the best known of them.
+    let mut notes: HashMap<i64, (String, ReviewSchedule)> = HashMap::new();
+    for row in rows {
+        let position = match field {
+            NoteField::Name(_) => match positions.get(&row.try_get::<i64, _>("mid")?) {
+                Some(position) => *position,
+                None => continue
+            },
+            NoteField::Position(position) => position - 1
+        };
+        let fields: String = row.try_get("flds")?;
+        let Some(text) = fields.split(FIELD_SEPARATOR).nth(position) else {
+            continue;
+        };
+
+        let card = Card {
+            card_type: row.try_get("type")?,
+            due: row.try_get("due")?,
+            interval: row.try_get("ivl")?,
+            factor: row.try_get("factor")?,
+            reps: row.try_get("reps")?
+        };
+        let Some(schedule) = card.schedule(created) else {
+            continue;
+        };
+
+        let note_id: i64 = row.try_get("note_id")?;
+        if notes.get(&note_id).is_none_or(|(_, best)| schedule.duration > best.duration) {
+            notes.insert(note_id, (text.to_string(), schedule));
+        }
+    }
+
+    Ok(notes.into_values()
+        .filter_map(|(field, schedule)| {
+            let (text, reading) = clean_field(&field, true);
+            (!text.is_empty()).then_some(KnownWord { text, reading, schedule: Some(schedule) })
+        })
+        .collect())
+}
+
+// Carry over the schedules of the words in an Anki collection.anki2 or .apkg, so the user doesn't start over with words they've been studying.
+pub async fn import_anki(knowledge: &Knowledge, path: &Path, field: &NoteField, language: Language) -> KnowledgeResult<KnownWordsReport> {
+    let mut magic = [0; 16];
+    File::open(path)?.read_exact(&mut magic)
+        .map_err(|_| KnowledgeError::InvalidImport("Not an Anki collection or .apkg file".to_string()))?;
+
+    let extracted = match &magic {
+        b"SQLite format 3\0" => None,
+        magic if magic.starts_with(b"PK\x03\x04") => Some(extract_collection(path).map_err(KnowledgeError::InvalidImport)?),
+        _ => return Err(KnowledgeError::InvalidImport("Not an Anki collection or .apkg file".to_string()))
+    };
+    let collection_path = extracted.as_ref().map_or(path, |extracted| extracted.0.as_path());
+
+    let mut connection = SqliteConnectOptions::new()
+        .filename(collection_path)
+        .read_only(true)
+        .connect().await?;
+    let words = read_words(&mut connection, field).await;
+    connection.close().await?;
+    let words = words?;
+    log::info!("Found {} studied notes in {}", words.len(), path.display());
+
+    // Every word has its own schedule, so there's no default interval.
+    knowledge.mark_known_words(language, &words, 0).await
+}
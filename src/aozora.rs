@@ -0,0 +1,262 @@
+use crate::knowledge::{Knowledge, KnowledgeResult, AddTextReport, DocumentPart};
+use crate::language::Language;
+use crate::reading_hint::Ruby;
+
+// A work from Aozora Bunko (https://www.aozora.gr.jp/) with its markup taken out.
+pub struct AozoraText {
+    pub title: String,
+    pub author: Option<String>,
+    // A part for each paragraph, with the ruby that was in it.
+    pub paragraphs: Vec<DocumentPart>
+}
+
+// Aozora Bunko texts are Shift_JIS, but copies that have been converted to UTF-8 are common too.
+pub fn decode(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.trim_start_matches('\u{FEFF}').to_string(),
+        Err(_) => encoding_rs::SHIFT_JIS.decode(bytes).0.into_owned()
+    }
+}
+
+pub fn parse(text: &str) -> AozoraText {
+    let text = text.replace("\r\n", "\n");
+    let lines: Vec<&str> = text.lines().collect();
+
+    // The title and author come first, then a blank line.
+    let header_end = lines.iter().position(|line| line.trim().is_empty()).unwrap_or(0);
+    let title = lines.first().map(|line| line.trim().to_string()).unwrap_or_default();
+    let author = lines.get(1).filter(|_| header_end > 1).map(|line| line.trim().to_string());
+
+    let mut body = &lines[header_end..];
+
+    // Most texts then explain the markup between two lines of dashes.
+    let is_rule = |line: &&str| line.len() > 10 && line.chars().all(|c| c == '-');
+    if let Some(first) = body.iter().position(is_rule) {
+        let explanation_start = body[..first].iter().all(|line| line.trim().is_empty());
+        if let (true, Some(second)) = (explanation_start, body[first + 1..].iter().position(is_rule)) {
+            body = &body[first + second + 2..];
+        }
+    }
+
+    // And end with where the text came from and who typed it up.
+    if let Some(footer) = body.iter().position(|line| line.starts_with("底本：") || line.starts_with("底本:")) {
+        body = &body[..footer];
+    }
+
+    let paragraphs = body.iter()
+        .map(|line| parse_line(line))
+        .filter(|paragraph| !paragraph.text.trim().is_empty())
+        .collect();
+
+    AozoraText { title, author, paragraphs }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum CharClass {
+    Kanji,
+    Hiragana,
+    Katakana,
+    Latin,
+    Other
+}
+
+fn char_class(c: char) -> CharClass {
+    match c {
+        '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' | '\u{F900}'..='\u{FAFF}' | '\u{20000}'..='\u{2FFFF}'
+            | '々' | '〆' | '〇' | 'ヶ' | 'ヵ' | '〻' => CharClass::Kanji,
+        '\u{3041}'..='\u{309F}' => CharClass::Hiragana,
+        '\u{30A0}'..='\u{30FF}' => CharClass::Katakana,
+        c if c.is_ascii_alphanumeric() => CharClass::Latin,
+        'Ａ'..='Ｚ' | 'ａ'..='ｚ' | '０'..='９' => CharClass::Latin,
+        _ => CharClass::Other
+    }
+}
+
+// Take the markup out of a line, keeping the ruby.
+// 漢字《かんじ》 is ruby on the run of characters of the same kind before it, ｜ marks where the run starts when it's something else,
+// and ［＃...］ is an annotation about the layout that isn't part of the text. A ※ with an annotation straight after it is a
+// character that isn't in Shift_JIS, the annotation says which one it is.
+fn parse_line(line: &str) -> DocumentPart {
+    let chars: Vec<char> = line.chars().collect();
+    let mut text: Vec<char> = Vec::new();
+    let mut ruby = Vec::new();
+    // Where the base of the next ruby starts, if it's been marked.
+    let mut base_start: Option<usize> = None;
+
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '｜' | '|' => {
+                base_start = Some(text.len());
+                i += 1;
+            },
+            '《' => {
+                let Some(length) = chars[i + 1..].iter().position(|c| *c == '》') else {
+                    text.push(chars[i]);
+                    i += 1;
+                    continue;
+                };
+                let reading: String = chars[i + 1..i + 1 + length].iter().collect();
+                i += length + 2;
+
+                let start = base_start.take().unwrap_or_else(|| {
+                    let class = text.last().map(|c| char_class(*c)).unwrap_or(CharClass::Other);
+                    match class {
+                        CharClass::Other => text.len(),
+                        class => text.len() - text.iter().rev().take_while(|c| char_class(**c) == class).count()
+                    }
+                });
+
+                let base: String = text[start..].iter().collect();
+                if !base.is_empty() && !reading.is_empty() {
+                    ruby.push(Ruby { base, reading });
+                }
+            },
+            '※' if chars.get(i + 1) == Some(&'［') && chars.get(i + 2) == Some(&'＃') => {
+                let (annotation, length) = annotation(&chars[i + 1..]);
+                if let Some(c) = gaiji(&annotation) {
+                    text.push(c);
+                }
+                i += 1 + length;
+            },
+            '［' if chars.get(i + 1) == Some(&'＃') => {
+                let (_, length) = annotation(&chars[i..]);
+                i += length;
+            },
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    DocumentPart {
+        text: text.into_iter().collect(),
+        timing: None,
+        ruby
+    }
+}
+
+// The contents of an annotation at the start of the text, and how many characters it takes up including the brackets.
+// Annotations can have other annotations' brackets inside them, e.g. ［＃「※［＃...］」は縦中横］.
+fn annotation(chars: &[char]) -> (String, usize) {
+    let mut depth = 0;
+    for (i, c) in chars.iter().enumerate() {
+        match c {
+            '［' => depth += 1,
+            '］' => {
+                depth -= 1;
+                if depth == 0 {
+                    return (chars[2..i].iter().collect(), i + 1);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // An annotation that's never closed runs to the end of the line.
+    (chars.iter().skip(2).collect(), chars.len())
+}
+
+// The character a gaiji annotation describes, when it gives its code point, e.g. ［＃「てへん＋劣」、U+6318、135-上-9］.
+fn gaiji(annotation: &str) -> Option<char> {
+    let (_, code) = annotation.split_once("U+")?;
+    let hex: String = code.chars().take_while(char::is_ascii_hexdigit).collect();
+    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)
+}
+
+// Add the sentences of an Aozora Bunko text, with its ruby as reading hints.
+pub async fn import_aozora(knowledge: &mut Knowledge, bytes: &[u8], source: Option<&str>) -> KnowledgeResult<AddTextReport> {
+    let text = parse(&decode(bytes));
+    let source = match (source, &text.author) {
+        (Some(source), _) => source.to_string(),
+        (None, Some(author)) => format!("{} - {}", text.title, author),
+        (None, None) => text.title.clone()
+    };
+    log::info!("Importing {} paragraphs of {}", text.paragraphs.len(), source);
+
+    knowledge.add_document(&text.paragraphs, &source, Language::Japanese).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ruby(part: &DocumentPart) -> Vec<(&str, &str)> {
+        part.ruby.iter().map(|ruby| (ruby.base.as_str(), ruby.reading.as_str())).collect()
+    }
+
+    #[test]
+    fn decodes_shift_jis_and_utf8() {
+        let (shift_jis, _, _) = encoding_rs::SHIFT_JIS.encode("吾輩は猫である");
+        assert_eq!(decode(&shift_jis), "吾輩は猫である");
+        assert_eq!(decode("\u{FEFF}吾輩は猫である".as_bytes()), "吾輩は猫である");
+    }
+
+    #[test]
+    fn takes_out_the_header_explanation_and_footer() {
+        let text = "吾輩は猫である\r\n夏目漱石\r\n\r\n-------------------------------------------------------\r\n【テキスト中に現れる記号について】\r\n《》：ルビ\r\n-------------------------------------------------------\r\n\r\n　吾輩は猫である。\r\n　名前はまだ無い。\r\n\r\n底本：「夏目漱石全集1」\r\n入力：someone\r\n";
+        let parsed = parse(text);
+
+        assert_eq!(parsed.title, "吾輩は猫である");
+        assert_eq!(parsed.author.as_deref(), Some("夏目漱石"));
+        let paragraphs: Vec<&str> = parsed.paragraphs.iter().map(|part| part.text.as_str()).collect();
+        assert_eq!(paragraphs, ["　吾輩は猫である。", "　名前はまだ無い。"]);
+    }
+
+    #[test]
+    fn a_header_without_an_author() {
+        let parsed = parse("題名\n\n本文。");
+
+        assert_eq!(parsed.title, "題名");
+        assert_eq!(parsed.author, None);
+        assert_eq!(parsed.paragraphs.len(), 1);
+    }
+
+    #[test]
+    fn ruby_covers_the_run_of_kanji_before_it() {
+        let part = parse_line("吾輩《わがはい》は猫《ねこ》である");
+
+        assert_eq!(part.text, "吾輩は猫である");
+        assert_eq!(ruby(&part), [("吾輩", "わがはい"), ("猫", "ねこ")]);
+    }
+
+    #[test]
+    fn ruby_covers_a_run_of_katakana_or_letters() {
+        let part = parse_line("そのカフェ《かふぇ》でＡＢＣ《えーびーしー》");
+
+        assert_eq!(part.text, "そのカフェでＡＢＣ");
+        assert_eq!(ruby(&part), [("カフェ", "かふぇ"), ("ＡＢＣ", "えーびーしー")]);
+    }
+
+    #[test]
+    fn a_bar_marks_where_the_ruby_starts() {
+        let part = parse_line("この｜東京駅《とうきょうえき》は一ヶ月《いっかげつ》");
+
+        assert_eq!(part.text, "この東京駅は一ヶ月");
+        assert_eq!(ruby(&part), [("東京駅", "とうきょうえき"), ("一ヶ月", "いっかげつ")]);
+    }
+
+    #[test]
+    fn takes_out_annotations() {
+        let part = parse_line("［＃ここから２字下げ］本文［＃「本文」に傍点］です［＃ここで字下げ終わり］");
+
+        assert_eq!(part.text, "本文です");
+        assert!(part.ruby.is_empty());
+    }
+
+    #[test]
+    fn gaiji_with_a_code_point_are_put_back() {
+        let part = parse_line("※［＃「てへん＋劣」、U+6318、135-上-9］る");
+        assert_eq!(part.text, "挘る");
+
+        let part = parse_line("※［＃「りっしんべん＋（匚＜夾）」、第3水準1-84-56］い");
+        assert_eq!(part.text, "い");
+    }
+
+    #[test]
+    fn unclosed_markup_is_left_as_text() {
+        assert_eq!(parse_line("猫《ねこ").text, "猫《ねこ");
+        assert_eq!(parse_line("猫［＃傍点").text, "猫");
+    }
+}
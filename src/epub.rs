@@ -0,0 +1,210 @@
+use std::{collections::HashMap, io::{Read, Seek}};
+
+use zip::ZipArchive;
+
+use crate::html::HtmlDocument;
+use crate::knowledge::{Knowledge, KnowledgeResult, KnowledgeError, AddTextReport, DocumentPart};
+use crate::language::Language;
+
+pub struct Chapter {
+    pub title: String,
+    pub paragraphs: Vec<DocumentPart>
+}
+
+// The readable text of an EPUB book, chapter by chapter in reading order.
+pub struct Book {
+    pub title: String,
+    pub chapters: Vec<Chapter>
+}
+
+impl Book {
+    pub fn read<R: Read + Seek>(reader: R) -> Result<Self, String> {
+        let mut archive = ZipArchive::new(reader).map_err(|e| format!("Not an EPUB file: {}", e))?;
+
+        // The container points at the package document, which lists everything else.
+        let container = read_file(&mut archive, "META-INF/container.xml")?;
+        let container = parse_xml(&container)?;
+        let package_path = container.descendants()
+            .find(|node| node.has_tag_name("rootfile"))
+            .and_then(|node| node.attribute("full-path"))
+            .ok_or("The container doesn't say where the package document is")?
+            .to_string();
+
+        let package = read_file(&mut archive, &package_path)?;
+        let package = parse_xml(&package)?;
+
+        let title = package.descendants()
+            .find(|node| node.has_tag_name("title"))
+            .and_then(|node| node.text())
+            .map(|title| title.trim().to_string())
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| "Untitled".to_string());
+
+        struct Item {
+            path: String,
+            properties: String
+        }
+        let items: HashMap<&str, Item> = package.descendants()
+            .filter(|node| node.has_tag_name("item"))
+            .filter_map(|node| Some((node.attribute("id")?, Item {
+                path: resolve(&package_path, node.attribute("href")?),
+                properties: node.attribute("properties").unwrap_or_default().to_string()
+            })))
+            .collect();
+
+        // Chapter titles come from the table of contents, either an EPUB 3 navigation document or an EPUB 2 NCX.
+        let spine = package.descendants().find(|node| node.has_tag_name("spine"));
+        let nav_path = items.values()
+            .find(|item| item.properties.split_whitespace().any(|property| property == "nav"))
+            .map(|item| item.path.clone());
+        let ncx_path = spine
+            .and_then(|spine| spine.attribute("toc"))
+            .and_then(|id| items.get(id))
+            .map(|item| item.path.clone());
+        let mut toc = HashMap::new();
+        if let Some(path) = &ncx_path {
+            toc.extend(read_ncx(&read_file(&mut archive, path)?, path));
+        }
+        if let Some(path) = &nav_path {
+            toc.extend(read_nav(&read_file(&mut archive, path)?, path));
+        }
+
+        let mut chapters = Vec::new();
+        let itemrefs = spine.iter().flat_map(|spine| spine.children()).filter(|node| node.has_tag_name("itemref"));
+        for itemref in itemrefs {
+            // Non-linear items are things like footnotes and pop-ups that aren't part of the main text.
+            if itemref.attribute("linear") == Some("no") {
+                continue;
+            }
+            let Some(item) = itemref.attribute("idref").and_then(|id| items.get(id)) else {
+                continue;
+            };
+            if Some(&item.path) == nav_path.as_ref() {
+                continue;
+            }
+
+            let document = HtmlDocument::parse(&read_file(&mut archive, &item.path)?);
+            let paragraphs = document.paragraphs();
+            if paragraphs.is_empty() {
+                continue;
+            }
+
+            // A lot of books give every page the book's title, which doesn't say much about the chapter.
+            let chapter_title = toc.get(&item.path).cloned()
+                .or_else(|| document.title().filter(|chapter_title| *chapter_title != title))
+                .unwrap_or_else(|| format!("Chapter {}", chapters.len() + 1));
+
+            chapters.push(Chapter { title: chapter_title, paragraphs });
+        }
+
+        Ok(Self { title, chapters })
+    }
+
+    // Add every chapter as its own document, with the book and chapter as the source.
+    // Each paragraph is a part of its own so a sentence never runs on from one paragraph into the next.
+    pub async fn add_to(&self, knowledge: &mut Knowledge, language: Language) -> KnowledgeResult<AddTextReport> {
+        let mut report = AddTextReport::default();
+        for chapter in &self.chapters {
+            let source = format!("{} - {}", self.title, chapter.title);
+            report.extend(knowledge.add_document(&chapter.paragraphs, &source, language).await?);
+        }
+
+        Ok(report)
+    }
+}
+
+// Read an EPUB and add all of its chapters.
+pub async fn import_epub<R: Read + Seek>(knowledge: &mut Knowledge, reader: R, language: Language) -> KnowledgeResult<AddTextReport> {
+    let book = Book::read(reader).map_err(KnowledgeError::InvalidImport)?;
+    log::info!("Importing {} chapters of {}", book.chapters.len(), book.title);
+    book.add_to(knowledge, language).await
+}
+
+fn read_file<R: Read + Seek>(archive: &mut ZipArchive<R>, path: &str) -> Result<String, String> {
+    let mut file = archive.by_name(path).map_err(|e| format!("Couldn't find {} in the book: {}", path, e))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+    Ok(contents)
+}
+
+fn parse_xml(source: &str) -> Result<roxmltree::Document<'_>, String> {
+    roxmltree::Document::parse(source).map_err(|e| format!("Invalid XML: {}", e))
+}
+
+// The chapter titles in an EPUB 2 table of contents.
+fn read_ncx(source: &str, path: &str) -> HashMap<String, String> {
+    let Ok(ncx) = parse_xml(source) else {
+        return HashMap::new();
+    };
+
+    let mut toc = HashMap::new();
+    for point in ncx.descendants().filter(|node| node.has_tag_name("navPoint")) {
+        let label = point.children()
+            .find(|node| node.has_tag_name("navLabel"))
+            .and_then(|label| label.descendants().find(|node| node.has_tag_name("text")))
+            .and_then(|text| text.text());
+        let src = point.children()
+            .find(|node| node.has_tag_name("content"))
+            .and_then(|content| content.attribute("src"));
+
+        if let (Some(label), Some(src)) = (label, src) {
+            // The first entry for a file is the chapter, any others are sections inside it.
+            toc.entry(resolve(path, src)).or_insert_with(|| label.trim().to_string());
+        }
+    }
+    toc
+}
+
+// The chapter titles in an EPUB 3 navigation document.
+fn read_nav(source: &str, path: &str) -> HashMap<String, String> {
+    let nav = scraper::Html::parse_document(source);
+    let selector = scraper::Selector::parse("nav a[href]").expect("valid selector");
+
+    let mut toc = HashMap::new();
+    for link in nav.select(&selector) {
+        let label = link.text().collect::<String>().trim().to_string();
+        if let (false, Some(href)) = (label.is_empty(), link.value().attr("href")) {
+            toc.entry(resolve(path, href)).or_insert(label);
+        }
+    }
+    toc
+}
+
+// Where a link in the file at `base` points to inside the book, without any fragment.
+fn resolve(base: &str, href: &str) -> String {
+    let href = percent_decode(href.split('#').next().unwrap_or_default());
+
+    let mut parts: Vec<&str> = base.split('/').collect();
+    // Links are relative to the directory the file is in.
+    parts.pop();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {},
+            ".." => { parts.pop(); },
+            part => parts.push(part)
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,230 @@
+use scraper::{Html, Node, ElementRef};
+use ego_tree::NodeRef;
+
+use crate::knowledge::{Knowledge, KnowledgeResult, AddTextReport, DocumentPart};
+use crate::language::Language;
+use crate::reading_hint::Ruby;
+
+// Elements whose text isn't part of what's being read. <rt> and <rp> are furigana, the base text of a <ruby> is kept.
+const SKIPPED: [&str; 10] = ["head", "script", "style", "noscript", "template", "svg", "nav", "rt", "rp", "math"];
+
+// Elements that start a new line, so paragraphs don't get run together into one sentence.
+const BLOCKS: [&str; 27] = [
+    "p", "div", "br", "hr", "li", "ul", "ol", "dl", "dt", "dd", "h1", "h2", "h3", "h4", "h5", "h6",
+    "blockquote", "pre", "table", "tr", "section", "article", "header", "footer", "aside", "figure", "figcaption"
+];
+
+// Parts of a web page that are the same on every page of the site rather than what's being read.
+// Only left out when we can't find the page's main content, an article's own header is worth keeping.
+const PAGE_CHROME: [&str; 6] = ["header", "footer", "aside", "form", "button", "dialog"];
+
+// A parsed (X)HTML document.
+pub struct HtmlDocument {
+    html: Html
+}
+
+impl HtmlDocument {
+    pub fn parse(source: &str) -> Self {
+        Self { html: Html::parse_document(source) }
+    }
+
+    // The document's <title>, or its first heading if it doesn't have one.
+    pub fn title(&self) -> Option<String> {
+        ["title", "h1", "h2", "h3"].iter()
+            .filter_map(|name| self.html.root_element().descendants()
+                .filter_map(ElementRef::wrap)
+                .find(|element| element.value().name() == *name))
+            .map(|element| collapse_whitespace(&element.text().collect::<String>()).trim().to_string())
+            .find(|title| !title.is_empty())
+    }
+
+    // The readable text of the body, with a line for each paragraph.
+    pub fn text(&self) -> String {
+        self.paragraphs()
+            .into_iter()
+            .map(|part| part.text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // The paragraphs of the whole body with the ruby in them.
+    pub fn paragraphs(&self) -> Vec<DocumentPart> {
+        let mut paragraphs = Paragraphs::default();
+        push_node(*self.html.root_element(), false, &mut paragraphs);
+        paragraphs.finish()
+    }
+
+    // The paragraphs of a web page's main content with the ruby in them, leaving out the site's navigation, headers and footers.
+    pub fn main_paragraphs(&self) -> Vec<DocumentPart> {
+        let elements = || self.html.root_element().descendants().filter_map(ElementRef::wrap);
+        let main = elements()
+            .find(|element| element.value().name() == "main" || element.value().attr("role") == Some("main"))
+            .or_else(|| elements()
+                .filter(|element| element.value().name() == "article")
+                .max_by_key(|element| element.text().map(str::len).sum::<usize>()));
+
+        let mut paragraphs = Paragraphs::default();
+        match main {
+            Some(main) => push_node(*main, false, &mut paragraphs),
+            None => push_node(*self.html.root_element(), true, &mut paragraphs)
+        }
+        paragraphs.finish()
+    }
+}
+
+// The paragraphs of a document, built up as its nodes are walked.
+#[derive(Default)]
+struct Paragraphs {
+    parts: Vec<DocumentPart>,
+    text: String,
+    ruby: Vec<Ruby>
+}
+
+impl Paragraphs {
+    fn end_paragraph(&mut self) {
+        let text = self.text.trim();
+        if !text.is_empty() {
+            self.parts.push(DocumentPart {
+                text: text.to_string(),
+                timing: None,
+                ruby: std::mem::take(&mut self.ruby)
+            });
+        }
+        self.text.clear();
+    }
+
+    fn finish(mut self) -> Vec<DocumentPart> {
+        self.end_paragraph();
+        self.parts
+    }
+}
+
+fn push_node(node: NodeRef<Node>, skip_page_chrome: bool, paragraphs: &mut Paragraphs) {
+    match node.value() {
+        Node::Text(t) => paragraphs.text.push_str(&collapse_whitespace(t)),
+        Node::Element(element) => {
+            let name = element.name();
+            if SKIPPED.contains(&name) || (skip_page_chrome && PAGE_CHROME.contains(&name)) {
+                return;
+            }
+            if name == "ruby" {
+                push_ruby(node, skip_page_chrome, paragraphs);
+                return;
+            }
+
+            let block = BLOCKS.contains(&name);
+            if block {
+                paragraphs.end_paragraph();
+            }
+            for child in node.children() {
+                push_node(child, skip_page_chrome, paragraphs);
+            }
+            if block {
+                paragraphs.end_paragraph();
+            }
+        },
+        _ => {
+            for child in node.children() {
+                push_node(child, skip_page_chrome, paragraphs);
+            }
+        }
+    }
+}
+
+// Ruby is the base text with the reading of it in an <rt> after it, and can have several of each like <ruby>漢<rt>かん</rt>字<rt>じ</rt></ruby>.
+// The base is part of the text and the readings are kept for the tokenizer.
+fn push_ruby(node: NodeRef<Node>, skip_page_chrome: bool, paragraphs: &mut Paragraphs) {
+    let mut base = String::new();
+    for child in node.children() {
+        match child.value().as_element().map(|element| element.name()) {
+            Some("rt") => {
+                let reading = ElementRef::wrap(child)
+                    .map(|rt| collapse_whitespace(&rt.text().collect::<String>()).trim().to_string())
+                    .unwrap_or_default();
+                if !base.trim().is_empty() && !reading.is_empty() {
+                    paragraphs.ruby.push(Ruby { base: base.trim().to_string(), reading });
+                }
+                base.clear();
+            },
+            Some("rp" | "rtc") => {},
+            _ => {
+                let start = paragraphs.text.len();
+                push_node(child, skip_page_chrome, paragraphs);
+                base.push_str(paragraphs.text.get(start..).unwrap_or_default());
+            }
+        }
+    }
+}
+
+// Line breaks in the markup don't mean anything, only the elements do.
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_whitespace = false;
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            if !in_whitespace {
+                collapsed.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            collapsed.push(c);
+            in_whitespace = false;
+        }
+    }
+    collapsed
+}
+
+// Saved pages are usually UTF-8, otherwise they say what they are in a <meta> near the top.
+fn decode(bytes: &[u8]) -> String {
+    if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(bytes) {
+        return encoding.decode_with_bom_removal(bytes).0.into_owned();
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.to_string();
+    }
+
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(2048)]).to_lowercase();
+    let encoding = head.find("charset=")
+        .map(|start| head[start + "charset=".len()..]
+            .trim_start_matches(['"', '\''])
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect::<String>())
+        .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+        .unwrap_or(encoding_rs::WINDOWS_1252);
+    encoding.decode(bytes).0.into_owned()
+}
+
+// Add the main content of a saved web page, with its <title> (or failing that the file's name) as the source if it isn't given one.
+pub async fn import_html(knowledge: &mut Knowledge, bytes: &[u8], source: Option<&str>, file_name: Option<&str>,
+                         language: Language) -> KnowledgeResult<AddTextReport> {
+    // The parsed document can't be held onto while adding, it isn't Send.
+    let (source, paragraphs) = {
+        let document = HtmlDocument::parse(&decode(bytes));
+        let source = source.map(str::to_string)
+            .or_else(|| document.title())
+            .or_else(|| file_name.map(str::to_string))
+            .unwrap_or_else(|| "Untitled".to_string());
+        (source, document.main_paragraphs())
+    };
+    log::info!("Importing {} paragraphs of {}", paragraphs.len(), source);
+
+    knowledge.add_document(&paragraphs, &source, language).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_paragraph_is_its_own_part() {
+        let document = HtmlDocument::parse("<html><body><h1>一</h1><p>吾輩は<ruby>猫<rt>ねこ</rt></ruby>である</p><p>名前はまだ無い</p></body></html>");
+        let paragraphs = document.paragraphs();
+
+        let texts: Vec<&str> = paragraphs.iter().map(|part| part.text.as_str()).collect();
+        assert_eq!(texts, ["一", "吾輩は猫である", "名前はまだ無い"]);
+        assert_eq!(paragraphs[1].ruby.len(), 1);
+        assert_eq!((paragraphs[1].ruby[0].base.as_str(), paragraphs[1].ruby[0].reading.as_str()), ("猫", "ねこ"));
+        assert_eq!(document.text(), "一\n吾輩は猫である\n名前はまだ無い");
+    }
+}
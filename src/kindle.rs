@@ -0,0 +1,243 @@
+use std::{collections::HashSet, io::Read, path::Path};
+
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Connection, Row};
+
+use crate::knowledge::{Knowledge, KnowledgeResult, KnowledgeError, AddTextReport, DocumentPart};
+use crate::language::Language;
+
+// What's between the entries of My Clippings.txt.
+const CLIPPING_SEPARATOR: &str = "==========";
+
+// How the line about a clipping starts when it's a note the user typed rather than a highlight, in the languages Kindles come in.
+const NOTE_MARKERS: [&str; 6] = ["- Your Note", "のメモ", "- Ihre Notiz", "- Tu nota", "- Votre note", "- La tua nota"];
+
+// A sentence from a book, with the title of the book.
+pub struct Clipping {
+    pub book: String,
+    pub text: String
+}
+
+// A word looked up in the Kindle's dictionary, with the sentence it was looked up in.
+pub struct Lookup {
+    // As it was written in the book.
+    pub word: String,
+    // Its dictionary form.
+    pub stem: String,
+    pub usage: Clipping
+}
+
+pub struct KindleReport {
+    pub sentences: AddTextReport,
+    // How many different words were looked up.
+    pub words_looked_up: usize
+}
+
+// The highlights in My Clippings.txt. Each entry is the book's title and author, a line about where it is and when it was made,
+// a blank line and then the text, e.g.
+//   Book Title (Author)
+//   - Your Highlight on page 12 | Location 123-125 | Added on Monday, 1 January 2024 12:00:00
+//
+//   The highlighted text.
+//   ==========
+// Bookmarks don't have any text, and notes are the user's own words rather than the book's, so they're left out.
+pub fn parse_clippings(contents: &str) -> Vec<Clipping> {
+    contents.replace("\r\n", "\n")
+        .split(CLIPPING_SEPARATOR)
+        .filter_map(|entry| {
+            let mut lines = entry.trim_start_matches(['\n', '\u{FEFF}']).lines();
+            let book = lines.next()?.trim_start_matches('\u{FEFF}').trim();
+            let about = lines.next()?;
+            if NOTE_MARKERS.iter().any(|marker| about.contains(marker)) {
+                return None;
+            }
+
+            let text = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+            (!book.is_empty() && !text.is_empty()).then(|| Clipping { book: book_title(book), text })
+        })
+        .collect()
+}
+
+// The title from the "Title (Author)" line at the top of a clipping.
+fn book_title(line: &str) -> String {
+    let Some(without_author) = line.strip_suffix([')', '）']) else {
+        return line.to_string();
+    };
+
+    // Titles can have brackets in them too, so find the one that goes with the last.
+    let mut depth = 0;
+    for (i, c) in without_author.char_indices().rev() {
+        match c {
+            ')' | '）' => depth += 1,
+            '(' | '（' if depth == 0 => return Some(without_author[..i].trim())
+                .filter(|title| !title.is_empty())
+                .unwrap_or(line)
+                .to_string(),
+            '(' | '（' => depth -= 1,
+            _ => {}
+        }
+    }
+    line.to_string()
+}
+
+// The words looked up in the language from a Kindle's vocab.db (in system/vocabulary on the Kindle), in the order they were looked up.
+pub async fn read_lookups(path: &Path, language: Language) -> KnowledgeResult<Vec<Lookup>> {
+    let mut connection = SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .connect().await?;
+
+    let rows = sqlx::query("
+        SELECT WORDS.word, WORDS.stem, LOOKUPS.usage, BOOK_INFO.title
+        FROM LOOKUPS
+            INNER JOIN WORDS ON WORDS.id = LOOKUPS.word_key
+            LEFT JOIN BOOK_INFO ON BOOK_INFO.id = LOOKUPS.book_key
+        WHERE WORDS.lang = ?
+        ORDER BY LOOKUPS.timestamp")
+        .bind(language.code())
+        .fetch_all(&mut connection).await;
+    connection.close().await?;
+
+    let mut lookups = Vec::new();
+    for row in rows? {
+        let word: Option<String> = row.try_get("word")?;
+        let stem: Option<String> = row.try_get("stem")?;
+        let usage: Option<String> = row.try_get("usage")?;
+        let book: Option<String> = row.try_get("title")?;
+
+        let (Some(word), Some(usage)) = (word, usage) else {
+            continue;
+        };
+        lookups.push(Lookup {
+            stem: stem.filter(|stem| !stem.is_empty()).unwrap_or_else(|| word.clone()),
+            word,
+            usage: Clipping {
+                book: book.filter(|book| !book.is_empty()).unwrap_or_else(|| "Kindle".to_string()),
+                text: usage.trim().to_string()
+            }
+        });
+    }
+
+    Ok(lookups)
+}
+
+// Add the sentences, with the clippings from each book kept together and in order so they can be shown in context.
+async fn add_clippings(knowledge: &mut Knowledge, clippings: &[&Clipping], language: Language) -> KnowledgeResult<AddTextReport> {
+    let mut books: Vec<(&str, Vec<DocumentPart>)> = Vec::new();
+    for clipping in clippings {
+        let part = DocumentPart { text: clipping.text.clone(), timing: None, ruby: Vec::new() };
+        match books.iter_mut().find(|(book, _)| *book == clipping.book) {
+            Some((_, parts)) => parts.push(part),
+            None => books.push((&clipping.book, vec![part]))
+        }
+    }
+
+    let mut report = AddTextReport::default();
+    for (book, parts) in books {
+        log::info!("Importing {} clippings from {}", parts.len(), book);
+        report.extend(knowledge.add_document(&parts, book, language).await?);
+    }
+
+    Ok(report)
+}
+
+// Add the highlights from My Clippings.txt or the sentences words were looked up in from vocab.db, whichever the file is.
+// The words looked up are flagged so they're learnt before other new words.
+pub async fn import_kindle(knowledge: &mut Knowledge, path: &Path, language: Language) -> KnowledgeResult<KindleReport> {
+    let mut magic = [0; 16];
+    let is_database = std::fs::File::open(path)?.read_exact(&mut magic).is_ok() && &magic == b"SQLite format 3\0";
+    if !is_database {
+        let contents = std::fs::read_to_string(path)?;
+        let clippings = parse_clippings(&contents);
+        if clippings.is_empty() {
+            return Err(KnowledgeError::InvalidImport("There aren't any highlights in the file".to_string()));
+        }
+
+        return Ok(KindleReport {
+            sentences: add_clippings(knowledge, &clippings.iter().collect::<Vec<_>>(), language).await?,
+            words_looked_up: 0
+        });
+    }
+
+    let lookups = read_lookups(path, language).await?;
+    log::info!("Found {} {} words looked up", lookups.len(), language.name());
+
+    // Flag the words first, so they're flagged as their sentences are added.
+    let mut words: Vec<String> = lookups.iter()
+        .flat_map(|lookup| [lookup.stem.clone(), lookup.word.clone()])
+        .collect();
+    words.sort();
+    words.dedup();
+    knowledge.mark_priority_words(language, &words).await?;
+    let words_looked_up = lookups.iter().map(|lookup| &lookup.stem).collect::<HashSet<_>>().len();
+
+    let usages: Vec<&Clipping> = lookups.iter().map(|lookup| &lookup.usage).collect();
+    Ok(KindleReport {
+        sentences: add_clippings(knowledge, &usages, language).await?,
+        words_looked_up
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_the_author_off_the_title() {
+        assert_eq!(book_title("吾輩は猫である (夏目漱石)"), "吾輩は猫である");
+        assert_eq!(book_title("吾輩は猫である（夏目漱石）"), "吾輩は猫である");
+        assert_eq!(book_title("Dune (Herbert, Frank)"), "Dune");
+    }
+
+    #[test]
+    fn keeps_brackets_that_are_part_of_the_title() {
+        assert_eq!(book_title("Dune (Book 1) (Herbert, Frank)"), "Dune (Book 1)");
+        assert_eq!(book_title("Title (Author (Editor))"), "Title");
+    }
+
+    #[test]
+    fn titles_without_an_author_are_left_alone() {
+        assert_eq!(book_title("Just a Title"), "Just a Title");
+        assert_eq!(book_title("(Only an Author)"), "(Only an Author)");
+        assert_eq!(book_title("Unbalanced)"), "Unbalanced)");
+    }
+
+    #[test]
+    fn parses_clippings() {
+        let contents = "\u{FEFF}吾輩は猫である (夏目漱石)\r\n\
+            - 位置No. 12-13のハイライト |作成日: 2024年1月1日月曜日 12:00:00\r\n\
+            \r\n\
+            吾輩は猫である。名前はまだ無い。\r\n\
+            ==========\r\n\
+            吾輩は猫である (夏目漱石)\r\n\
+            - 位置No. 20のメモ |作成日: 2024年1月1日月曜日 12:01:00\r\n\
+            \r\n\
+            My own note\r\n\
+            ==========\r\n\
+            Dune (Herbert, Frank)\r\n\
+            - Your Bookmark on Location 100 | Added on Monday, 1 January 2024 12:02:00\r\n\
+            \r\n\
+            \r\n\
+            ==========\r\n\
+            Dune (Herbert, Frank)\r\n\
+            - Your Highlight on Location 120-121 | Added on Monday, 1 January 2024 12:03:00\r\n\
+            \r\n\
+            Fear is the mind-killer.\r\n\
+            ==========\r\n";
+        let clippings = parse_clippings(contents);
+
+        let found: Vec<(&str, &str)> = clippings.iter().map(|clipping| (clipping.book.as_str(), clipping.text.as_str())).collect();
+        assert_eq!(found, [("吾輩は猫である", "吾輩は猫である。名前はまだ無い。"), ("Dune", "Fear is the mind-killer.")]);
+    }
+
+    #[test]
+    fn skips_notes_in_other_languages() {
+        let contents = "Buch (Autor)\n- Ihre Notiz bei Position 5\n\nMeine Notiz\n==========\n";
+        assert!(parse_clippings(contents).is_empty());
+    }
+
+    #[test]
+    fn nothing_to_parse() {
+        assert!(parse_clippings("").is_empty());
+        assert!(parse_clippings("==========\n==========\n").is_empty());
+    }
+}
@@ -1,12 +1,17 @@
-use std::{collections::{HashSet, HashMap}, str::FromStr, future::Future, process::{Command, Stdio}, io::{Write}, string, fmt::Display};
+use std::{collections::{HashSet, HashMap}, str::FromStr, fmt::Display, sync::Arc, path::{Path, PathBuf}};
 
-use askama::Error;
 use log::info;
-use sqlx::{sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow}, ConnectOptions, SqliteConnection, Pool, Sqlite, Row, Transaction, Executor, SqliteExecutor, error::DatabaseError};
-use lindera::tokenizer::Tokenizer;
-use chrono::{Utc, Duration, FixedOffset, Local, Timelike, format::Fixed, DateTime};
+use sqlx::{sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow}, SqliteConnection, Pool, Sqlite, Row};
+use chrono::{Duration, FixedOffset, Local, Timelike, DateTime};
 use futures::TryStreamExt;
 
+use crate::tokenizer::{Tokenizer, Morpheme, PartOfSpeech, to_hiragana};
+use crate::user_dictionary::{UserDictionary, UserDictionaryEntry};
+use crate::normalize::NormalizationMode;
+use crate::language::Language;
+use crate::segmenter::{Segmenter, SegmenterConfig, RejectedSentence};
+use crate::reading_hint::{self, Ruby, ReadingHint};
+
 // https://supermemo.guru/wiki/SuperMemo_1.0_for_DOS_(1987)#Algorithm_SM-2
 #[derive(Debug)]
 struct SuperMemoItem {
@@ -44,7 +49,7 @@ fn super_memo_2(item: SuperMemoItem, response_quality: f64) -> SuperMemoItem {
             duration: Duration::days(1),
             e_factor: item.e_factor
         },
-        r => {
+        _ => {
             let e_factor = (item.e_factor + (0.1 - (5.0 - response_quality) * (0.08 + (5.0 - response_quality) * 0.02))).max(1.3);
             let duration = mul_duration(item.duration, e_factor);
             let repitition = repitition + 1;
@@ -58,253 +63,1721 @@ fn super_memo_2(item: SuperMemoItem, response_quality: f64) -> SuperMemoItem {
     }
 }
 
-// A lookup table for word frequency.
-#[derive(Clone)]
-struct WordFrequencyList {
-    words: HashMap<String, i64>
-}
+// A lookup table for word frequency.
+#[derive(Clone)]
+struct WordFrequencyList {
+    words: HashMap<String, i64>
+}
+
+impl WordFrequencyList {
+    // The frequency list for a language, from a file with one word per line, most frequent first.
+    // Only Japanese has a list built in, other languages without one treat every word as equally rare.
+    fn for_language(language: Language, path: Option<&Path>) -> KnowledgeResult<Self> {
+        Ok(match (path, language) {
+            (Some(path), _) => Self::from_list(&std::fs::read_to_string(path)?),
+            (None, Language::Japanese) => Self::from_list(include_str!("japanese_word_frequency.txt")),
+            (None, _) => Self::from_list("")
+        })
+    }
+
+    fn from_list(wordlist: &str) -> Self {
+        let mut words = HashMap::new();
+        for (index, line) in wordlist.lines().enumerate() {
+            words.insert(line.to_string(), index as i64);
+        }
+
+        Self { 
+            words
+        }
+    }
+
+    // This may be a little confusing, but this function returns the words rank in the frequency list.
+    // Infrequent words will have higher values and frequent words will have lower values.
+    fn get_word_freq(&self, word: &str) -> i64 {
+        match self.words.get(word) {
+            Some(freq) => *freq,
+            None => self.words.len() as i64 // If it's not on the list if must be very infrequent
+                                            // Treat it as though it's at the bottom of the list.
+        }
+    }
+}
+
+// What identifies a word: its dictionary form, how that's read and roughly what kind of word it is.
+// That way homographs like 方 (かた/ほう) get their own schedules.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WordKey {
+    pub text: String,
+    pub reading: String,
+    pub pos: String
+}
+
+impl WordKey {
+    fn from_morpheme(morpheme: &Morpheme) -> Self {
+        Self {
+            text: morpheme.dictionary_form.clone(),
+            reading: morpheme.lemma_reading(),
+            pos: morpheme.part_of_speech().as_str().to_string()
+        }
+    }
+
+    fn from_row(row: &SqliteRow) -> KnowledgeResult<Self> {
+        Ok(Self {
+            text: row.try_get("text")?,
+            reading: row.try_get("reading")?,
+            pos: row.try_get("pos")?
+        })
+    }
+}
+
+impl Display for WordKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.reading.is_empty() {
+            write!(f, "{} [{}]", self.text, self.pos)
+        } else {
+            write!(f, "{} ({}) [{}]", self.text, self.reading, self.pos)
+        }
+    }
+}
+
+// A word as it's shown to the user.
+pub struct WordData {
+    pub id: i64,
+    pub text: String,
+    pub reading: String,
+    // The representative form of the group of spellings this word belongs to, e.g. 分かる/わかる for 解る.
+    pub lemma_group: Option<String>
+}
+
+impl WordData {
+    fn from_row(row: &SqliteRow) -> KnowledgeResult<Self> {
+        Ok(Self {
+            id: row.try_get("word_id")?,
+            text: row.try_get("word_text")?,
+            reading: row.try_get("word_reading")?,
+            lemma_group: row.try_get("word_lemma_group")?
+        })
+    }
+}
+
+// A token as it appears in a sentence.
+pub struct TokenData {
+    // The reading of the surface text in hiragana.
+    pub reading: String,
+    // The span of the sentence the token covers, in characters.
+    pub offset: usize,
+    pub end_offset: usize,
+    pub word_id: Option<i64>,
+    // Whether the token's word is never reviewed (particles and the like).
+    pub excluded: bool
+}
+
+pub struct IPlusOneSentenceData {
+    pub sentence_text: String,
+    pub sentence_id: i64,
+    pub sentence_source: String,
+    // Where the sentence is in its source, for sentences from subtitles.
+    pub sentence_timing: Option<SentenceTiming>,
+    pub sentence_translation: Option<String>,
+    pub tokens: Vec<TokenData>,
+    pub words_being_reviewed: Vec<WordData>,
+    pub words_that_are_new: Vec<WordData>
+}
+
+// A word that would no longer be in any sentence after retokenizing.
+pub struct DisappearingWord {
+    pub key: WordKey,
+    // Whether the word has been reviewed, and would be leaving its progress behind.
+    pub reviewed: bool,
+    pub next_review_at: Option<String>,
+    // Words from before readings were stored hand their progress over to words with the same spelling.
+    pub progress_carried_over: bool
+}
+
+// A sentence whose words would change after retokenizing.
+pub struct SentenceChange {
+    pub sentence_id: i64,
+    pub text: String,
+    pub added: Vec<WordKey>,
+    pub removed: Vec<WordKey>
+}
+
+// What a retokenize would change, without actually changing anything.
+#[derive(Default)]
+pub struct RetokenizeReport {
+    pub sentences_checked: usize,
+    pub sentences_failed: Vec<(i64, String)>,
+    pub words_appearing: Vec<WordKey>,
+    pub words_disappearing: Vec<DisappearingWord>,
+    pub changed_sentences: Vec<SentenceChange>
+}
+
+impl Display for RetokenizeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |keys: &[WordKey]| keys.iter().map(|key| key.to_string()).collect::<Vec<_>>().join(", ");
+
+        writeln!(f, "Checked {} sentences.", self.sentences_checked)?;
+
+        writeln!(f, "\n{} sentences couldn't be tokenized:", self.sentences_failed.len())?;
+        for (id, error) in &self.sentences_failed {
+            writeln!(f, "  #{}: {}", id, error)?;
+        }
+
+        writeln!(f, "\n{} words would appear:", self.words_appearing.len())?;
+        for key in &self.words_appearing {
+            writeln!(f, "  {}", key)?;
+        }
+
+        let with_progress = self.words_disappearing.iter().filter(|word| word.reviewed && !word.progress_carried_over).count();
+        writeln!(f, "\n{} words would disappear ({} of them would leave SRS progress behind):", self.words_disappearing.len(), with_progress)?;
+        for word in &self.words_disappearing {
+            write!(f, "  {}", word.key)?;
+            if word.reviewed {
+                match word.next_review_at.as_deref() {
+                    Some(next_review_at) => write!(f, " - reviewed, next review at {}", next_review_at)?,
+                    None => write!(f, " - reviewed")?
+                }
+                if word.progress_carried_over {
+                    write!(f, " (progress carried over to the new readings)")?;
+                }
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "\n{} sentences would change:", self.changed_sentences.len())?;
+        for change in &self.changed_sentences {
+            writeln!(f, "  #{} {}", change.sentence_id, change.text)?;
+            if !change.added.is_empty() {
+                writeln!(f, "    + {}", join(&change.added))?;
+            }
+            if !change.removed.is_empty() {
+                writeln!(f, "    - {}", join(&change.removed))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// A token the user has picked for a sentence, anything left out comes from the tokenizer.
+pub struct TokenOverride {
+    pub surface: String,
+    pub dictionary_form: Option<String>,
+    pub reading: Option<String>,
+    pub pos: Option<PartOfSpeech>
+}
+
+// A token of a sentence as it's currently tokenized, for correcting it.
+pub struct EditableToken {
+    pub surface: String,
+    pub word_text: Option<String>,
+    pub word_reading: Option<String>,
+    pub word_pos: Option<String>,
+    // What the user has set for the token, if anything.
+    pub dictionary_form: Option<String>,
+    pub reading: Option<String>,
+    pub pos: Option<String>
+}
+
+pub struct SentenceTokensData {
+    pub sentence_id: i64,
+    pub sentence_text: String,
+    pub tokens: Vec<EditableToken>,
+    // Whether the tokens have been corrected by hand.
+    pub overridden: bool
+}
+
+// When a sentence is said in a video, in milliseconds from the start.
+#[derive(Clone, Copy, Debug)]
+pub struct SentenceTiming {
+    pub start_ms: i64,
+    pub end_ms: i64
+}
+
+impl SentenceTiming {
+    fn from_row(row: &SqliteRow) -> KnowledgeResult<Option<Self>> {
+        let start_ms: Option<i64> = row.try_get("start_time")?;
+        let end_ms: Option<i64> = row.try_get("end_time")?;
+        Ok(start_ms.map(|start_ms| Self { start_ms, end_ms: end_ms.unwrap_or(start_ms) }))
+    }
+}
+
+// A word from a list of words the user already knows.
+pub struct KnownWord {
+    pub text: String,
+    // Only the word with this reading is known, or any word with the same spelling if there isn't one.
+    pub reading: Option<String>,
+    // The schedule the word had in another app, e.g. Anki, to carry on with instead of the default for known words.
+    pub schedule: Option<ReviewSchedule>
+}
+
+pub struct ReviewSchedule {
+    pub repitition: u32,
+    pub e_factor: f64,
+    pub duration: Duration,
+    pub next_review_at: DateTime<FixedOffset>
+}
+
+pub struct KnownWordsReport {
+    pub entries: usize,
+    // How many of the words we already had were marked as known.
+    pub words_marked: u64
+}
+
+// A piece of a document to split into sentences, e.g. a subtitle.
+pub struct DocumentPart {
+    pub text: String,
+    // Every sentence in the part is given the same timing.
+    pub timing: Option<SentenceTiming>,
+    // Furigana that came with the text, in the order it appears.
+    pub ruby: Vec<Ruby>
+}
+
+// The sentences around a sentence in the text it came from, in order.
+#[derive(Default)]
+pub struct SentenceContext {
+    pub before: Vec<String>,
+    pub after: Vec<String>
+}
+
+pub struct ReviewInfoData {
+    pub reviews_remaining: i64
+}
+
+#[derive(Default)]
+pub struct AddTextReport {
+    pub sentences_added: i64,
+    // Sentences that were left out for being too short or too long.
+    pub sentences_rejected: Vec<RejectedSentence>
+}
+
+impl AddTextReport {
+    // Add up the reports from adding several texts.
+    pub fn extend(&mut self, other: AddTextReport) {
+        self.sentences_added += other.sentences_added;
+        self.sentences_rejected.extend(other.sentences_rejected);
+    }
+}
+
+#[derive(Debug)]
+pub enum KnowledgeError {
+    DatabaseError(sqlx::Error),
+    MigrationError(sqlx::migrate::MigrateError),
+    IoError(std::io::Error),
+    // The tokenizer failed for some other reason.
+    TokenizerError(String),
+    // The tokenizer's executable couldn't be found.
+    TokenizerNotFound(String),
+    // The tokenizer process died, with whatever it wrote to stderr.
+    TokenizerCrashed(String),
+    // The tokenizer took too long on a sentence.
+    TokenizerTimedOut(std::time::Duration),
+    // The tokenizer gave us something we couldn't make sense of.
+    TokenizerInvalidOutput(String),
+    SentenceNotFound(i64),
+    // A language code in the database that we don't know about.
+    UnknownLanguage(String),
+    // Tokens given for a sentence don't match up with its text.
+    InvalidTokens(String),
+    // A file being imported isn't what it's meant to be.
+    InvalidImport(String),
+    // The database needs migrating but was opened read only.
+    DatabaseOutOfDate
+}
+
+impl Display for KnowledgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DatabaseError(e) => write!(f, "Database error! Error: {}", e),
+            Self::MigrationError(e) => write!(f, "Migration error! Error: {}", e),
+            Self::IoError(e) => write!(f, "IO error! Error: {}", e),
+            Self::TokenizerError(e) => write!(f, "Error tokenizing sentence! Error: {}", e),
+            Self::TokenizerNotFound(name) => write!(f, "Couldn't find the tokenizer '{}'! Is it installed and on the PATH?", name),
+            Self::TokenizerCrashed(stderr) => write!(f, "The tokenizer crashed! Output: {}", stderr),
+            Self::TokenizerTimedOut(timeout) => write!(f, "The tokenizer took longer than {} seconds on a sentence!", timeout.as_secs()),
+            Self::TokenizerInvalidOutput(output) => write!(f, "The tokenizer gave invalid output! Output: {}", output),
+            Self::SentenceNotFound(id) => write!(f, "There's no sentence with id {}!", id),
+            Self::UnknownLanguage(code) => write!(f, "Unknown language '{}'!", code),
+            Self::InvalidTokens(reason) => write!(f, "Invalid tokens! {}", reason),
+            Self::InvalidImport(reason) => write!(f, "Couldn't import the file! {}", reason),
+            Self::DatabaseOutOfDate => write!(f, "The database is out of date and can't be updated while it's read only! Run once without --retokenize-dry-run first.")
+        }
+    }
+}
+
+impl std::error::Error for KnowledgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::DatabaseError(e) => Some(e),
+            Self::MigrationError(e) => Some(e),
+            Self::IoError(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<sqlx::Error> for KnowledgeError {
+    fn from(value: sqlx::Error) -> Self {
+        KnowledgeError::DatabaseError(value)
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for KnowledgeError {
+    fn from(value: sqlx::migrate::MigrateError) -> Self {
+        KnowledgeError::MigrationError(value)
+    }
+}
+
+impl From<std::io::Error> for KnowledgeError {
+    fn from(value: std::io::Error) -> Self {
+        KnowledgeError::IoError(value)
+    }
+}
+
+pub type KnowledgeResult<T> = Result<T, KnowledgeError>;
+
+// Dictionary forms that a tokenizer change has turned into different ones, e.g. 分かる -> わかる.
+// Loaded from a file with an old and a new dictionary form on each line separated by a tab.
+#[derive(Default)]
+pub struct LemmaMap {
+    pairs: Vec<(String, String)>
+}
+
+impl LemmaMap {
+    pub fn load(path: &Path) -> KnowledgeResult<Self> {
+        let pairs = std::fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (old, new) = line.split_once('\t')?;
+                Some((old.trim().to_string(), new.trim().to_string()))
+            })
+            .collect();
+
+        Ok(Self { pairs })
+    }
+}
+
+// Keeps track of which new words took the place of reviewed words while retokenizing,
+// by looking at which new tokens cover the same bit of a sentence the old ones did.
+#[derive(Default)]
+struct LemmaRemapper {
+    // Old word id -> new word id -> how many characters of the old word's tokens the new word covers.
+    votes: HashMap<i64, HashMap<i64, usize>>
+}
+
+impl LemmaRemapper {
+    fn record(&mut self, old_spans: &[(usize, usize, i64)], new_spans: &[(usize, usize, i64)]) {
+        for &(old_start, old_end, old_word) in old_spans {
+            for &(new_start, new_end, new_word) in new_spans {
+                let overlap = old_end.min(new_end).saturating_sub(old_start.max(new_start));
+                if overlap > 0 && old_word != new_word {
+                    *self.votes.entry(old_word).or_default().entry(new_word).or_default() += overlap;
+                }
+            }
+        }
+    }
+
+    // The word that most often replaced the old word.
+    fn best_match(&self, old_word: i64) -> Option<i64> {
+        self.votes.get(&old_word)?
+            .iter()
+            .max_by_key(|(new_word, votes)| (**votes, -**new_word))
+            .map(|(new_word, _)| *new_word)
+    }
+}
+
+// Settings for how knowledge is gathered.
+#[derive(Clone)]
+pub struct KnowledgeConfig {
+    // Words with these parts of speech are never reviewed or counted as new.
+    pub excluded_pos: HashSet<PartOfSpeech>,
+    // How sentences and words are normalized before they're stored.
+    pub normalization: NormalizationMode,
+    // Frequency lists to use instead of the built in one (or lack of one) for a language.
+    pub frequency_lists: HashMap<Language, PathBuf>,
+    // How added text is split up into sentences.
+    pub segmenter: SegmenterConfig,
+    // Open the database read only and don't bring it up to date with the rest of the config, for seeing what a change would do.
+    pub read_only: bool
+}
+
+impl Default for KnowledgeConfig {
+    fn default() -> Self {
+        Self {
+            excluded_pos: HashSet::from(PartOfSpeech::DEFAULT_EXCLUDED),
+            normalization: NormalizationMode::Japanese,
+            frequency_lists: HashMap::new(),
+            segmenter: SegmenterConfig::default(),
+            read_only: false
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Knowledge {
+    word_freq: Arc<HashMap<Language, WordFrequencyList>>,
+    tokenizers: Arc<HashMap<Language, Arc<dyn Tokenizer>>>,
+    user_dictionary: Arc<UserDictionary>,
+    config: KnowledgeConfig,
+    connection: Pool<Sqlite>
+}
+
+impl Knowledge {
+    pub async fn new(tokenizers: HashMap<Language, Arc<dyn Tokenizer>>, config: KnowledgeConfig) -> Result<Self, KnowledgeError> {
+        // Create the database.
+        let connection = SqlitePoolOptions::new()
+            .connect_with(SqliteConnectOptions::from_str("db.sqlite").unwrap() // TODO: error handling
+                .create_if_missing(!config.read_only)
+                .read_only(config.read_only)
+            )
+            .await?;
+
+        // Run migrations, or make sure there aren't any to run if we can't write.
+        let migrator = sqlx::migrate!();
+        if config.read_only {
+            let applied: HashSet<i64> = sqlx::query("SELECT version FROM _sqlx_migrations WHERE success")
+                .fetch_all(&connection).await
+                .map_err(|_| KnowledgeError::DatabaseOutOfDate)?
+                .into_iter()
+                .map(|row| row.get("version"))
+                .collect();
+            if migrator.iter().any(|migration| !applied.contains(&migration.version)) {
+                return Err(KnowledgeError::DatabaseOutOfDate);
+            }
+        }
+        else {
+            migrator.run(&connection).await?;
+        }
+
+        let mut word_freq = HashMap::new();
+        for language in Language::ALL {
+            let path = config.frequency_lists.get(&language);
+            word_freq.insert(language, WordFrequencyList::for_language(language, path.map(PathBuf::as_path))?);
+        }
+
+        let mut knowledge = Self {
+            word_freq: Arc::new(word_freq),
+            tokenizers: Arc::new(tokenizers),
+            user_dictionary: Arc::default(),
+            config,
+            connection
+        };
+        knowledge.reload_user_dictionary().await?;
+        if !knowledge.config.read_only {
+            knowledge.apply_excluded_pos().await?;
+            knowledge.apply_variant_groups().await?;
+        }
+
+        Ok(knowledge)
+    }
+    
+    // Mark words as excluded from reviews (or not) depending on their part of speech.
+    // This is run on startup so that changes to the policy apply to words we already have.
+    async fn apply_excluded_pos(&self) -> KnowledgeResult<()> {
+        let mut tx = self.connection.begin().await?;
+        for pos in PartOfSpeech::ALL {
+            sqlx::query("UPDATE words SET excluded = ? WHERE pos = ?")
+                .bind(self.config.excluded_pos.contains(&pos))
+                .bind(pos.as_str())
+                .execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn retokenize(&mut self, lemma_map: &LemmaMap) -> KnowledgeResult<()> {
+        log::info!("Retokenizing sentences...");
+
+        // First open a transaction.
+        let mut tx = self.connection.begin().await?;
+
+        // Now clear out the word_sentence table.
+        log::info!("Clearing out word_sentence relationships...");
+        sqlx::query("DELETE FROM word_sentence")
+            .execute(&mut *tx).await?; // TODO: handle errors
+
+        // Set the word tables count to 0 for everything
+        log::info!("Setting all words count to 0...");
+        sqlx::query("UPDATE words SET count = 0")
+            .execute(&mut *tx).await?; // TODO: handle errors
+
+        // Now go through each sentence and re-tokenize it
+        log::info!("Iterating through all sentences and retokenizing...");
+        let mut sentences_to_process = Vec::new();
+        {
+            let mut sentences_stream = sqlx::query("SELECT id, text, source FROM sentences")
+                .fetch(&mut *tx);
+
+            while let Some(row) = sentences_stream.try_next().await? { // TODO: error handling
+                let sentence: String = row.try_get("text")?;
+                let id: i64 = row.try_get("id")?;
+
+                sentences_to_process.push((id, sentence));
+            }
+        }
+
+        // Now the stream is closed...
+        let mut remapper = LemmaRemapper::default();
+        for (id, text) in sentences_to_process {
+            // Tokenize
+            let words = self.tokenize_sentence(id, text.as_str(), &mut tx).await?;
+
+            // Re-add the sentences
+            self.retokenize_sentence(id, words, &mut remapper, &mut tx).await?;
+        }
+
+        self.split_legacy_words(&mut tx).await?;
+        self.remap_orphaned_words(lemma_map, &remapper, &mut tx).await?;
+
+        tx.commit().await?;
+
+        log::info!("Finished re-tokenizing");
+
+        // Done!
+        Ok(())
+    }
+
+    // Tokenize every sentence with the current tokenizer and work out what a retokenize would change.
+    // Nothing is written to the database.
+    pub async fn retokenize_dry_run(&self) -> KnowledgeResult<RetokenizeReport> {
+        log::info!("Working out what retokenizing would change...");
+
+        // The words each sentence currently has.
+        let mut current_words: HashMap<i64, HashSet<WordKey>> = HashMap::new();
+        let mut words_in_use: HashMap<WordKey, (bool, Option<String>)> = HashMap::new();
+        {
+            let mut rows = sqlx::query("
+                SELECT sentence_id, text, reading, pos, reviewed, next_review_at
+                FROM word_sentence
+                    INNER JOIN words ON words.id = word_id")
+                .fetch(&self.connection);
+
+            while let Some(row) = rows.try_next().await? {
+                let key = WordKey::from_row(&row)?;
+                current_words.entry(row.try_get("sentence_id")?).or_default().insert(key.clone());
+                words_in_use.insert(key, (row.try_get("reviewed")?, row.try_get("next_review_at")?));
+            }
+        }
+
+        let mut sentences = Vec::new();
+        {
+            let mut rows = sqlx::query("SELECT id, text FROM sentences ORDER BY id")
+                .fetch(&self.connection);
+
+            while let Some(row) = rows.try_next().await? {
+                sentences.push((row.try_get::<i64, _>("id")?, row.try_get::<String, _>("text")?));
+            }
+        }
+
+        // Now tokenize everything and compare.
+        let mut report = RetokenizeReport::default();
+        let mut new_words_in_use: HashSet<WordKey> = HashSet::new();
+        let mut connection = self.connection.acquire().await?;
+        for (id, text) in sentences {
+            report.sentences_checked += 1;
+
+            let morphemes = match self.tokenize_sentence(id, &text, &mut connection).await {
+                Ok(morphemes) => morphemes,
+                Err(e) => {
+                    // The sentence would keep its current words.
+                    report.sentences_failed.push((id, e.to_string()));
+                    new_words_in_use.extend(current_words.get(&id).into_iter().flatten().cloned());
+                    continue;
+                }
+            };
+
+            let new_words: HashSet<WordKey> = morphemes.iter().map(|morpheme| self.word_key(morpheme)).collect();
+            let old_words = current_words.remove(&id).unwrap_or_default();
+
+            let mut added: Vec<WordKey> = new_words.difference(&old_words).cloned().collect();
+            let mut removed: Vec<WordKey> = old_words.difference(&new_words).cloned().collect();
+            if !added.is_empty() || !removed.is_empty() {
+                added.sort();
+                removed.sort();
+                report.changed_sentences.push(SentenceChange { sentence_id: id, text, added, removed });
+            }
+
+            new_words_in_use.extend(new_words);
+        }
+
+        report.words_appearing = new_words_in_use.iter()
+            .filter(|key| !words_in_use.contains_key(key))
+            .cloned()
+            .collect();
+        report.words_appearing.sort();
+
+        let new_spellings: HashSet<&str> = new_words_in_use.iter().map(|key| key.text.as_str()).collect();
+        report.words_disappearing = words_in_use.into_iter()
+            .filter(|(key, _)| !new_words_in_use.contains(key))
+            .map(|(key, (reviewed, next_review_at))| DisappearingWord {
+                progress_carried_over: key.reading.is_empty() && new_spellings.contains(key.text.as_str()),
+                key,
+                reviewed,
+                next_review_at
+            })
+            .collect();
+        report.words_disappearing.sort_by(|a, b| b.reviewed.cmp(&a.reviewed).then_with(|| a.key.cmp(&b.key)));
+
+        Ok(report)
+    }
+
+    // Only retokenize sentences that were tokenized by a different tokenizer (or a different version of it).
+    // Sentences are done in chunks, each in its own transaction, so this can be stopped and picked up again later.
+    pub async fn retokenize_stale(&mut self, chunk_size: i64, lemma_map: &LemmaMap) -> KnowledgeResult<()> {
+        let tokenizers = self.tokenizers.clone();
+        for language in Language::ALL {
+            let Some(tokenizer) = tokenizers.get(&language) else {
+                continue;
+            };
+            self.retokenize_stale_language(language, tokenizer.as_ref(), chunk_size, lemma_map).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn retokenize_stale_language(&mut self, language: Language, tokenizer: &dyn Tokenizer, chunk_size: i64, lemma_map: &LemmaMap) -> KnowledgeResult<()> {
+        let tokenizer_name = tokenizer.name().to_string();
+        let tokenizer_version = tokenizer.version().to_string();
+
+        let total: i64 = sqlx::query("
+            SELECT COUNT(*) FROM sentences
+            WHERE language = ?
+                AND (tokenizer IS NOT ? OR tokenizer_version IS NOT ?)")
+            .bind(language.code())
+            .bind(&tokenizer_name)
+            .bind(&tokenizer_version)
+            .fetch_one(&self.connection).await?
+            .try_get(0)?;
+        if total == 0 {
+            return Ok(());
+        }
+        log::info!("Retokenizing {} {} sentences that weren't tokenized by {} {}...", total, language.name(), tokenizer_name, tokenizer_version);
+
+        // Go through in id order so that sentences that fail to tokenize get skipped rather than retried forever.
+        let mut last_id = 0;
+        let mut processed = 0;
+        let mut failed = 0;
+        let mut remapper = LemmaRemapper::default();
+        loop {
+            let mut chunk = Vec::new();
+            {
+                let mut sentences_stream = sqlx::query("
+                    SELECT id, text FROM sentences
+                    WHERE language = ?
+                        AND (tokenizer IS NOT ? OR tokenizer_version IS NOT ?)
+                        AND id > ?
+                    ORDER BY id
+                    LIMIT ?")
+                    .bind(language.code())
+                    .bind(&tokenizer_name)
+                    .bind(&tokenizer_version)
+                    .bind(last_id)
+                    .bind(chunk_size)
+                    .fetch(&self.connection);
+
+                while let Some(row) = sentences_stream.try_next().await? {
+                    chunk.push((row.try_get::<i64, _>("id")?, row.try_get::<String, _>("text")?));
+                }
+            }
+
+            if chunk.is_empty() {
+                break;
+            }
+
+            let mut tx = self.connection.begin().await?;
+            for (id, text) in chunk {
+                last_id = id;
+                processed += 1;
+
+                let words = match self.tokenize_sentence(id, text.as_str(), &mut tx).await {
+                    Ok(words) => words,
+                    Err(e) => {
+                        log::error!("Couldn't retokenize sentence {}, skipping it: {}", id, e);
+                        failed += 1;
+                        continue;
+                    }
+                };
+
+                self.remove_words_from_sentence(id, &mut tx).await?;
+                self.retokenize_sentence(id, words, &mut remapper, &mut tx).await?;
+            }
+
+            // Any reviewed words that have lost all their sentences by now have been replaced, pass their progress on.
+            self.split_legacy_words(&mut tx).await?;
+            self.remap_orphaned_words(lemma_map, &remapper, &mut tx).await?;
+            tx.commit().await?;
+
+            log::info!("Retokenized {}/{} sentences", processed, total);
+        }
+
+        log::info!("Finished re-tokenizing, {} sentences couldn't be tokenized", failed);
+
+        Ok(())
+    }
+
+    // What a morpheme is stored as, normalized the same way sentences are so that the tokenizer's
+    // output can't bring back the duplicates normalizing the sentence got rid of.
+    fn word_key(&self, morpheme: &Morpheme) -> WordKey {
+        let key = WordKey::from_morpheme(morpheme);
+        WordKey {
+            text: self.config.normalization.normalize(&key.text),
+            reading: to_hiragana(&self.config.normalization.normalize(&key.reading)),
+            pos: key.pos
+        }
+    }
+
+    // Normalize sentences and words that were stored before normalization (or with a different mode),
+    // merging any duplicates that turn up. The oldest sentence and the word with the most progress are kept.
+    pub async fn merge_duplicates(&mut self) -> KnowledgeResult<()> {
+        log::info!("Normalizing sentences and words and merging duplicates...");
+
+        let mut tx = self.connection.begin().await?;
+
+        // Sentences first, any that change get tokenized again from the normalized text.
+        let mut sentence_groups: HashMap<String, Vec<(i64, String)>> = HashMap::new();
+        {
+            let mut rows = sqlx::query("SELECT id, text FROM sentences ORDER BY id")
+                .fetch(&mut *tx);
+
+            while let Some(row) = rows.try_next().await? {
+                let text: String = row.try_get("text")?;
+                sentence_groups.entry(self.config.normalization.normalize(&text))
+                    .or_default()
+                    .push((row.try_get("id")?, text));
+            }
+        }
+
+        // Get rid of the duplicates before renaming anything so the UNIQUE(text) constraint doesn't get in the way.
+        // Hand corrected tokens and reading hints go by character offsets, so they can't be trusted once characters have moved around.
+        // The ones from a duplicate that's the same length as the normalized text are kept if the sentence kept doesn't have its own.
+        let mut merged_sentences = 0;
+        let mut dropped_corrections = 0;
+        let mut renamed_sentences = Vec::new();
+        for (normalized, sentences) in sentence_groups {
+            let length = normalized.chars().count();
+            let (keep_id, keep_text) = &sentences[0];
+            if keep_text.chars().count() != length {
+                dropped_corrections += self.drop_sentence_corrections(*keep_id, &mut tx).await?;
+            }
+
+            for (id, text) in &sentences[1..] {
+                self.remove_words_from_sentence(*id, &mut tx).await?;
+                sqlx::query("UPDATE document_sentences SET sentence_id = ? WHERE sentence_id = ?")
+                    .bind(keep_id)
+                    .bind(id)
+                    .execute(&mut *tx).await?;
+                if text.chars().count() == length {
+                    self.move_sentence_corrections(*id, *keep_id, &mut tx).await?;
+                }
+                dropped_corrections += self.drop_sentence_corrections(*id, &mut tx).await?;
+                sqlx::query("DELETE FROM sentences WHERE id = ?")
+                    .bind(id)
+                    .execute(&mut *tx).await?;
+                merged_sentences += 1;
+            }
+
+            if *keep_text != normalized {
+                renamed_sentences.push((*keep_id, normalized));
+            }
+        }
+
+        for (id, normalized) in &renamed_sentences {
+            sqlx::query("UPDATE sentences SET text = ? WHERE id = ?")
+                .bind(normalized)
+                .bind(id)
+                .execute(&mut *tx).await?;
+
+            self.refresh_sentence(*id, normalized, &mut tx).await?;
+        }
+
+        // Now the words, the one with the most progress in each group takes over the rest.
+        let mut word_groups: HashMap<(String, WordKey), Vec<(i64, WordKey)>> = HashMap::new();
+        {
+            let mut rows = sqlx::query("SELECT id, language, text, reading, pos FROM words ORDER BY reviewed DESC, repitition DESC, id")
+                .fetch(&mut *tx);
+
+            while let Some(row) = rows.try_next().await? {
+                let key = WordKey::from_row(&row)?;
+                let normalized = WordKey {
+                    text: self.config.normalization.normalize(&key.text),
+                    reading: to_hiragana(&self.config.normalization.normalize(&key.reading)),
+                    pos: key.pos.clone()
+                };
+                word_groups.entry((row.try_get("language")?, normalized))
+                    .or_default()
+                    .push((row.try_get("id")?, key));
+            }
+        }
+
+        let mut merged_words = 0;
+        for ((_, normalized), words) in word_groups {
+            let (keep_id, keep_key) = &words[0];
+            for (id, _) in &words[1..] {
+                sqlx::query("UPDATE OR IGNORE word_sentence SET word_id = ? WHERE word_id = ?")
+                    .bind(keep_id)
+                    .bind(id)
+                    .execute(&mut *tx).await?;
+                sqlx::query("UPDATE sentence_tokens SET word_id = ? WHERE word_id = ?")
+                    .bind(keep_id)
+                    .bind(id)
+                    .execute(&mut *tx).await?;
+                sqlx::query("UPDATE words SET count = count + (SELECT count FROM words WHERE id = ?) WHERE id = ?")
+                    .bind(id)
+                    .bind(keep_id)
+                    .execute(&mut *tx).await?;
+                sqlx::query("DELETE FROM words WHERE id = ?")
+                    .bind(id)
+                    .execute(&mut *tx).await?;
+                merged_words += 1;
+            }
+
+            if *keep_key != normalized {
+                sqlx::query("UPDATE words SET text = ?, reading = ? WHERE id = ?")
+                    .bind(&normalized.text)
+                    .bind(&normalized.reading)
+                    .bind(keep_id)
+                    .execute(&mut *tx).await?;
+            }
+        }
+
+        // The user's lists of words go the same way, the known word with the longest interval is the one kept.
+        let mut known_word_groups: HashMap<(String, String, String), Vec<i64>> = HashMap::new();
+        {
+            let mut rows = sqlx::query("
+                SELECT rowid, language, text, reading FROM known_words
+                ORDER BY COALESCE(review_duration, interval_days * 86400) DESC, rowid")
+                .fetch(&mut *tx);
+
+            while let Some(row) = rows.try_next().await? {
+                let text: String = row.try_get("text")?;
+                let reading: String = row.try_get("reading")?;
+                let normalized_text = self.config.normalization.normalize(&text);
+                let normalized_reading = to_hiragana(&self.config.normalization.normalize(&reading));
+                known_word_groups.entry((row.try_get("language")?, normalized_text, normalized_reading))
+                    .or_default()
+                    .push(row.try_get("rowid")?);
+            }
+        }
+
+        let mut merged_known_words = 0;
+        for ((_, text, reading), entries) in known_word_groups {
+            for rowid in &entries[1..] {
+                sqlx::query("DELETE FROM known_words WHERE rowid = ?")
+                    .bind(rowid)
+                    .execute(&mut *tx).await?;
+                merged_known_words += 1;
+            }
+
+            sqlx::query("UPDATE known_words SET text = ?, reading = ? WHERE rowid = ?")
+                .bind(&text)
+                .bind(&reading)
+                .bind(entries[0])
+                .execute(&mut *tx).await?;
+        }
+
+        let mut priority_word_groups: HashMap<(String, String), Vec<i64>> = HashMap::new();
+        {
+            let mut rows = sqlx::query("SELECT rowid, language, text FROM priority_words ORDER BY rowid")
+                .fetch(&mut *tx);
+
+            while let Some(row) = rows.try_next().await? {
+                let text: String = row.try_get("text")?;
+                priority_word_groups.entry((row.try_get("language")?, self.config.normalization.normalize(&text)))
+                    .or_default()
+                    .push(row.try_get("rowid")?);
+            }
+        }
+
+        let mut merged_priority_words = 0;
+        for ((_, text), entries) in priority_word_groups {
+            for rowid in &entries[1..] {
+                sqlx::query("DELETE FROM priority_words WHERE rowid = ?")
+                    .bind(rowid)
+                    .execute(&mut *tx).await?;
+                merged_priority_words += 1;
+            }
+
+            sqlx::query("UPDATE priority_words SET text = ? WHERE rowid = ?")
+                .bind(&text)
+                .bind(entries[0])
+                .execute(&mut *tx).await?;
+        }
+
+        // Words that only match the lists now they're normalized the same way.
+        let known_word_ids: Vec<i64> = sqlx::query("
+            SELECT words.id AS id
+            FROM words
+                INNER JOIN known_words ON known_words.language = words.language AND known_words.text = words.text
+                    AND (known_words.reading = '' OR known_words.reading = words.reading)
+            WHERE words.reviewed = FALSE")
+            .fetch_all(&mut *tx).await?
+            .iter()
+            .map(|row| row.try_get("id"))
+            .collect::<Result<_, _>>()?;
+        for word_id in known_word_ids {
+            self.apply_known_word(word_id, &mut tx).await?;
+        }
+        sqlx::query("
+            UPDATE words
+            SET priority = TRUE
+            WHERE priority = FALSE
+                AND EXISTS(SELECT 1 FROM priority_words WHERE priority_words.language = words.language AND priority_words.text = words.text)")
+            .execute(&mut *tx).await?;
+
+        tx.commit().await?;
+
+        log::info!("Merged {} duplicate sentences and {} duplicate words, normalized {} sentences",
+            merged_sentences, merged_words, renamed_sentences.len());
+        log::info!("Merged {} duplicate known words and {} duplicate priority words", merged_known_words, merged_priority_words);
+        if dropped_corrections > 0 {
+            log::warn!("Dropped {} corrected tokens and reading hints that no longer lined up with their sentences", dropped_corrections);
+        }
+
+        Ok(())
+    }
+
+    // Move a sentence's corrected tokens and reading hints over to another sentence with the same text,
+    // unless it has its own, which would clash with them.
+    async fn move_sentence_corrections(&self, from: i64, to: i64, tx: &mut SqliteConnection) -> KnowledgeResult<()> {
+        for table in ["sentence_token_overrides", "sentence_reading_hints"] {
+            sqlx::query(&format!("
+                UPDATE {table}
+                SET sentence_id = ?
+                WHERE sentence_id = ? AND NOT EXISTS (SELECT 1 FROM {table} WHERE sentence_id = ?)"))
+                .bind(to)
+                .bind(from)
+                .bind(to)
+                .execute(&mut *tx).await?;
+        }
+
+        Ok(())
+    }
+
+    // Get rid of a sentence's corrected tokens and reading hints, returning how many there were.
+    async fn drop_sentence_corrections(&self, id: i64, tx: &mut SqliteConnection) -> KnowledgeResult<u64> {
+        let mut dropped = 0;
+        for table in ["sentence_token_overrides", "sentence_reading_hints"] {
+            dropped += sqlx::query(&format!("DELETE FROM {table} WHERE sentence_id = ?"))
+                .bind(id)
+                .execute(&mut *tx).await?
+                .rows_affected();
+        }
+
+        Ok(dropped)
+    }
+
+    fn tokenizer(&self, language: Language) -> KnowledgeResult<&dyn Tokenizer> {
+        self.tokenizers.get(&language)
+            .map(Arc::as_ref)
+            .ok_or_else(|| KnowledgeError::TokenizerError(format!("There's no tokenizer for {}", language.name())))
+    }
+
+    async fn get_sentence_language(&self, id: i64, tx: &mut SqliteConnection) -> KnowledgeResult<Language> {
+        let code: String = sqlx::query("SELECT language FROM sentences WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut *tx).await?
+            .ok_or(KnowledgeError::SentenceNotFound(id))?
+            .try_get("language")?;
+
+        Language::from_code(&code).ok_or(KnowledgeError::UnknownLanguage(code))
+    }
+
+    // Split a sentence up with the language's tokenizer, then apply the user dictionary on top.
+    fn tokenize(&self, language: Language, sentence: &str) -> KnowledgeResult<Vec<Morpheme>> {
+        let morphemes = self.tokenizer(language)?.tokenize(sentence)?;
+        Ok(self.user_dictionary.apply(sentence, morphemes))
+    }
+
+    // Tokenize a sentence we already have, using the user's corrections for it if there are any.
+    async fn tokenize_sentence(&self, id: i64, sentence: &str, tx: &mut SqliteConnection) -> KnowledgeResult<Vec<Morpheme>> {
+        let language = self.get_sentence_language(id, tx).await?;
+
+        let mut overrides = Vec::new();
+        {
+            let mut rows = sqlx::query("
+                SELECT start_offset, end_offset, dictionary_form, reading, pos
+                FROM sentence_token_overrides
+                WHERE sentence_id = ?
+                ORDER BY start_offset")
+                .bind(id)
+                .fetch(&mut *tx);
+
+            while let Some(row) = rows.try_next().await? {
+                overrides.push((
+                    row.try_get::<i64, _>("start_offset")? as usize,
+                    row.try_get::<i64, _>("end_offset")? as usize,
+                    row.try_get::<Option<String>, _>("dictionary_form")?,
+                    row.try_get::<Option<String>, _>("reading")?,
+                    row.try_get::<Option<String>, _>("pos")?
+                ));
+            }
+        }
+
+        let mut hints = Vec::new();
+        {
+            let mut rows = sqlx::query("SELECT start_offset, end_offset, reading FROM sentence_reading_hints WHERE sentence_id = ? ORDER BY start_offset")
+                .bind(id)
+                .fetch(&mut *tx);
+
+            while let Some(row) = rows.try_next().await? {
+                hints.push(ReadingHint {
+                    start: row.try_get::<i64, _>("start_offset")? as usize,
+                    end: row.try_get::<i64, _>("end_offset")? as usize,
+                    reading: row.try_get("reading")?
+                });
+            }
+        }
+
+        if overrides.is_empty() {
+            return Ok(reading_hint::apply(sentence, self.tokenize(language, sentence)?, &hints));
+        }
+
+        // Tokens the user hasn't said anything about can still use what the tokenizer knows about them.
+        let analysed = match self.tokenize(language, sentence) {
+            Ok(morphemes) => reading_hint::apply(sentence, morphemes, &hints),
+            Err(e) => {
+                log::warn!("Couldn't tokenize sentence {}, only using the user's tokens: {}", id, e);
+                Vec::new()
+            }
+        };
+
+        let chars: Vec<char> = sentence.chars().collect();
+        let morphemes = overrides.into_iter().map(|(start, end, dictionary_form, reading, pos)| {
+            let surface: String = chars[start.min(chars.len())..end.min(chars.len())].iter().collect();
+            let same = analysed.iter().find(|morpheme| morpheme.offset == start && morpheme.surface == surface);
+
+            match (same, dictionary_form, reading, pos.as_deref().and_then(PartOfSpeech::from_name)) {
+                (Some(morpheme), None, None, None) => morpheme.clone(),
+                (same, dictionary_form, reading, pos) => {
+                    let dictionary_form = dictionary_form
+                        .or_else(|| same.map(|morpheme| morpheme.dictionary_form.clone()))
+                        .unwrap_or_else(|| surface.clone());
+                    let reading = reading.map(|reading| to_hiragana(&reading))
+                        .or_else(|| same.map(Morpheme::lemma_reading))
+                        .unwrap_or_else(|| to_hiragana(&dictionary_form));
+                    let pos = pos
+                        .or_else(|| same.map(Morpheme::part_of_speech))
+                        .unwrap_or(PartOfSpeech::Noun);
+
+                    let mut morpheme = Morpheme::user_defined(&surface, &dictionary_form, &reading, pos, start);
+                    if let Some(same) = same {
+                        // The tokenizer still knows better how the surface itself is read.
+                        morpheme.reading = same.reading.clone();
+                    }
+                    morpheme
+                }
+            }
+        }).collect();
+
+        Ok(morphemes)
+    }
+
+    // Load the user dictionary from the database, it's kept in memory as it's used for every sentence.
+    async fn reload_user_dictionary(&mut self) -> KnowledgeResult<()> {
+        let mut entries = Vec::new();
+        {
+            let mut rows = sqlx::query("SELECT pattern, reading, pos FROM user_dictionary")
+                .fetch(&self.connection);
+
+            while let Some(row) = rows.try_next().await? {
+                let pos: String = row.try_get("pos")?;
+                entries.push(UserDictionaryEntry {
+                    pattern: row.try_get("pattern")?,
+                    reading: row.try_get("reading")?,
+                    pos: PartOfSpeech::from_name(&pos).unwrap_or(PartOfSpeech::Noun)
+                });
+            }
+        }
+
+        self.user_dictionary = Arc::new(UserDictionary::new(entries));
+
+        Ok(())
+    }
+
+    // Add entries to the user dictionary from a file with an entry on each line, see UserDictionaryEntry::parse.
+    // Sentences the new entries appear in are marked as stale so that --retokenize-stale picks them up.
+    pub async fn load_user_dictionary(&mut self, path: &Path) -> KnowledgeResult<()> {
+        let contents = std::fs::read_to_string(path)?;
+
+        let mut tx = self.connection.begin().await?;
+        let mut count = 0;
+        let mut stale = 0;
+        for line in contents.lines().filter(|line| !line.trim().is_empty() && !line.starts_with('#')) {
+            let Some(mut entry) = UserDictionaryEntry::parse(line) else {
+                log::warn!("Ignoring invalid user dictionary line: {}", line);
+                continue;
+            };
+            entry.pattern = self.config.normalization.normalize(&entry.pattern);
+
+            sqlx::query("
+                INSERT INTO user_dictionary(pattern, reading, pos) VALUES(?, ?, ?)
+                ON CONFLICT(pattern) DO UPDATE SET reading = excluded.reading, pos = excluded.pos")
+                .bind(&entry.pattern)
+                .bind(&entry.reading)
+                .bind(entry.pos.as_str())
+                .execute(&mut *tx).await?;
+
+            stale += sqlx::query("UPDATE sentences SET tokenizer_version = NULL WHERE instr(text, ?) > 0")
+                .bind(entry.text())
+                .execute(&mut *tx).await?
+                .rows_affected();
+            count += 1;
+        }
+        tx.commit().await?;
+
+        log::info!("Loaded {} user dictionary entries, {} sentences need retokenizing with --retokenize-stale", count, stale);
+        self.reload_user_dictionary().await
+    }
+
+    // Get the tokens of a sentence along with any corrections the user has made to them.
+    pub async fn get_sentence_tokens(&self, sentence_id: i64) -> KnowledgeResult<SentenceTokensData> {
+        let sentence_text: String = sqlx::query("SELECT text FROM sentences WHERE id = ?")
+            .bind(sentence_id)
+            .fetch_optional(&self.connection).await?
+            .ok_or(KnowledgeError::SentenceNotFound(sentence_id))?
+            .try_get("text")?;
+
+        let mut rows = sqlx::query("
+            SELECT sentence_tokens.surface, words.text AS word_text, words.reading AS word_reading, words.pos AS word_pos,
+                overrides.start_offset IS NOT NULL AS overridden, overrides.dictionary_form, overrides.reading, overrides.pos
+            FROM sentence_tokens
+                LEFT JOIN words ON words.id = word_id
+                LEFT JOIN sentence_token_overrides AS overrides
+                    ON overrides.sentence_id = sentence_tokens.sentence_id AND overrides.start_offset = sentence_tokens.start_offset
+            WHERE sentence_tokens.sentence_id = ?
+            ORDER BY position")
+            .bind(sentence_id)
+            .fetch(&self.connection);
+
+        let mut tokens = Vec::new();
+        let mut overridden = false;
+        while let Some(row) = rows.try_next().await? {
+            overridden |= row.try_get::<bool, _>("overridden")?;
+            tokens.push(EditableToken {
+                surface: row.try_get("surface")?,
+                word_text: row.try_get("word_text")?,
+                word_reading: row.try_get("word_reading")?,
+                word_pos: row.try_get("word_pos")?,
+                dictionary_form: row.try_get("dictionary_form")?,
+                reading: row.try_get("reading")?,
+                pos: row.try_get("pos")?
+            });
+        }
+
+        Ok(SentenceTokensData { sentence_id, sentence_text, tokens, overridden })
+    }
+
+    // Correct how a sentence is split into words. The tokens have to cover the sentence in order, apart from whitespace.
+    // The corrections are kept and used from then on, including when everything is retokenized.
+    pub async fn set_sentence_tokens(&mut self, sentence_id: i64, tokens: Vec<TokenOverride>) -> KnowledgeResult<()> {
+        let sentence_text: String = sqlx::query("SELECT text FROM sentences WHERE id = ?")
+            .bind(sentence_id)
+            .fetch_optional(&self.connection).await?
+            .ok_or(KnowledgeError::SentenceNotFound(sentence_id))?
+            .try_get("text")?;
+
+        // Work out where each token is in the sentence.
+        let chars: Vec<char> = sentence_text.chars().collect();
+        let mut spans = Vec::new();
+        let mut cursor = 0;
+        for token in &tokens {
+            while chars.get(cursor).is_some_and(|c| c.is_whitespace()) {
+                cursor += 1;
+            }
+
+            let surface: Vec<char> = token.surface.chars().collect();
+            if surface.is_empty() || chars.get(cursor..cursor + surface.len()) != Some(&surface[..]) {
+                return Err(KnowledgeError::InvalidTokens(format!("'{}' isn't next in the sentence", token.surface)));
+            }
+
+            spans.push((cursor, cursor + surface.len()));
+            cursor += surface.len();
+        }
+        if chars[cursor..].iter().any(|c| !c.is_whitespace()) {
+            return Err(KnowledgeError::InvalidTokens("The tokens don't cover the whole sentence".to_string()));
+        }
+
+        let mut tx = self.connection.begin().await?;
+        sqlx::query("DELETE FROM sentence_token_overrides WHERE sentence_id = ?")
+            .bind(sentence_id)
+            .execute(&mut *tx).await?;
+
+        for (token, (start, end)) in tokens.iter().zip(spans) {
+            sqlx::query("
+                INSERT INTO sentence_token_overrides(sentence_id, start_offset, end_offset, dictionary_form, reading, pos)
+                VALUES(?, ?, ?, ?, ?, ?)")
+                .bind(sentence_id)
+                .bind(start as i64)
+                .bind(end as i64)
+                .bind(&token.dictionary_form)
+                .bind(&token.reading)
+                .bind(token.pos.map(|pos| pos.as_str()))
+                .execute(&mut *tx).await?;
+        }
+
+        self.refresh_sentence(sentence_id, &sentence_text, &mut tx).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Throw away the user's corrections to a sentence and go back to what the tokenizer says.
+    pub async fn clear_sentence_tokens(&mut self, sentence_id: i64) -> KnowledgeResult<()> {
+        let sentence_text: String = sqlx::query("SELECT text FROM sentences WHERE id = ?")
+            .bind(sentence_id)
+            .fetch_optional(&self.connection).await?
+            .ok_or(KnowledgeError::SentenceNotFound(sentence_id))?
+            .try_get("text")?;
+
+        let mut tx = self.connection.begin().await?;
+        sqlx::query("DELETE FROM sentence_token_overrides WHERE sentence_id = ?")
+            .bind(sentence_id)
+            .execute(&mut *tx).await?;
+
+        self.refresh_sentence(sentence_id, &sentence_text, &mut tx).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Tokenize a single sentence again, passing progress on from any reviewed words it loses.
+    async fn refresh_sentence(&mut self, id: i64, text: &str, tx: &mut SqliteConnection) -> KnowledgeResult<()> {
+        let words = self.tokenize_sentence(id, text, tx).await?;
+
+        let mut remapper = LemmaRemapper::default();
+        self.remove_words_from_sentence(id, tx).await?;
+        self.retokenize_sentence(id, words, &mut remapper, tx).await?;
+        self.remap_orphaned_words(&LemmaMap::default(), &remapper, tx).await?;
+
+        Ok(())
+    }
+
+    // Give a sentence its new words, keeping track of which new words took the place of reviewed words.
+    async fn retokenize_sentence(&mut self, id: i64, words: Vec<Morpheme>, remapper: &mut LemmaRemapper, tx: &mut SqliteConnection) -> KnowledgeResult<()> {
+        // Only reviewed words have any progress worth carrying over.
+        let old_spans = self.get_token_spans(id, true, tx).await?;
 
-impl WordFrequencyList {
-    fn new() -> Self {
-        let wordlist = include_str!("japanese_word_frequency.txt");
-        let mut words = HashMap::new();
-        for (index, line) in wordlist.lines().enumerate() {
-            words.insert(line.to_string(), index as i64);
-        }
+        self.add_words_to_sentence(id, words, tx).await?;
 
-        Self { 
-            words
+        if !old_spans.is_empty() {
+            let new_spans = self.get_token_spans(id, false, tx).await?;
+            remapper.record(&old_spans, &new_spans);
         }
+
+        Ok(())
     }
 
-    // This may be a little confusing, but this function returns the words rank in the frequency list.
-    // Infrequent words will have higher values and frequent words will have lower values.
-    fn get_word_freq(&self, word: &str) -> i64 {
-        match self.words.get(word) {
-            Some(freq) => *freq,
-            None => self.words.len() as i64 // If it's not on the list if must be very infrequent
-                                            // Treat it as though it's at the bottom of the list.
+    // Get the (start, end, word id) of the tokens in a sentence.
+    async fn get_token_spans(&self, sentence_id: i64, only_reviewed: bool, tx: &mut SqliteConnection) -> KnowledgeResult<Vec<(usize, usize, i64)>> {
+        let mut rows = sqlx::query("
+            SELECT start_offset, end_offset, word_id
+            FROM sentence_tokens
+                INNER JOIN words ON words.id = word_id
+            WHERE sentence_id = ?
+                AND (words.reviewed = TRUE OR ? = FALSE)")
+            .bind(sentence_id)
+            .bind(only_reviewed)
+            .fetch(&mut *tx);
+
+        let mut spans = Vec::new();
+        while let Some(row) = rows.try_next().await? {
+            spans.push((
+                row.try_get::<i64, _>("start_offset")? as usize,
+                row.try_get::<i64, _>("end_offset")? as usize,
+                row.try_get("word_id")?
+            ));
         }
-    }
-}
 
-// Try and split up a text into sentences.
-fn iterate_sentences(text: &str) -> Vec<String> {
-    let terminators: HashSet<char> = HashSet::from(['。', '\n', '！', '？']);
-    let open_quotes: HashSet<char> = HashSet::from(['「', '『', '（']);
-    let close_quotes: HashSet<char> = HashSet::from(['」', '』', '）']);
+        Ok(spans)
+    }
 
-    let mut depth: i32 = 0;
-    let mut curr_string: String = String::new();
-    let mut sentences = Vec::new();
-    for c in text.chars() {
-        curr_string.push(c);
+    // Reviewed words that don't appear in any sentence any more have most likely been lemmatized differently
+    // by the tokenizer (e.g. 分かる -> わかる). Hand their progress over to whichever new word replaced them,
+    // either from the user's lemma map or from the word that covers the same parts of the same sentences.
+    async fn remap_orphaned_words(&self, lemma_map: &LemmaMap, remapper: &LemmaRemapper, tx: &mut SqliteConnection) -> KnowledgeResult<()> {
+        let mut orphans = Vec::new();
+        {
+            let mut rows = sqlx::query("
+                SELECT id, text, reading FROM words
+                WHERE reviewed = TRUE
+                    AND NOT EXISTS (SELECT 1 FROM word_sentence WHERE word_id = words.id)")
+                .fetch(&mut *tx);
 
-        if open_quotes.contains(&c) {
-            depth += 1;
-        }
-        else if close_quotes.contains(&c) {
-            depth -= 1;
+            while let Some(row) = rows.try_next().await? {
+                orphans.push((row.try_get::<i64, _>("id")?, row.try_get::<String, _>("text")?, row.try_get::<String, _>("reading")?));
+            }
         }
-        else if depth == 0 && terminators.contains(&c) {
-            let sentence = curr_string.trim();
 
-            if !sentence.is_empty() {
-                sentences.push(sentence.to_string());
+        for (old_id, old_text, old_reading) in orphans {
+            // The user's mapping wins, prefer a new word with the same reading if there's a choice.
+            let mut new_id = None;
+            for (_, new_text) in lemma_map.pairs.iter().filter(|(from, _)| *from == old_text) {
+                new_id = sqlx::query("
+                    SELECT id FROM words
+                    WHERE text = ?
+                        AND language = (SELECT language FROM words WHERE id = ?)
+                        AND EXISTS (SELECT 1 FROM word_sentence WHERE word_id = words.id)
+                    ORDER BY reading = ? DESC, count DESC
+                    LIMIT 1")
+                    .bind(new_text)
+                    .bind(old_id)
+                    .bind(&old_reading)
+                    .fetch_optional(&mut *tx).await?
+                    .map(|row| row.try_get("id"))
+                    .transpose()?;
+
+                if new_id.is_some() {
+                    break;
+                }
             }
 
-            curr_string.clear();
+            let Some(new_id) = new_id.or_else(|| remapper.best_match(old_id)) else {
+                log::warn!("Couldn't find what replaced the reviewed word {} ({}), its progress has been left behind", old_text, old_reading);
+                continue;
+            };
+
+            if self.carry_over_progress(old_id, new_id, tx).await? {
+                log::info!("Carried progress on {} ({}) over to word id {}", old_text, old_reading, new_id);
+
+                sqlx::query("DELETE FROM words WHERE id = ?")
+                    .bind(old_id)
+                    .execute(&mut *tx).await?;
+            }
         }
-    }
-    sentences
-}
 
-pub struct IPlusOneSentenceData {
-    pub sentence_text: String,
-    pub sentence_id: i64,
-    pub sentence_source: String,
-    pub words_being_reviewed: Vec<(i64, String)>,
-    pub words_that_are_new: Vec<(i64, String)>
-}
+        Ok(())
+    }
 
-pub struct ReviewInfoData {
-    pub reviews_remaining: i64
-}
+    // Copy the review schedule of one word onto another, as long as the other word hasn't been reviewed itself.
+    // Returns whether anything was copied.
+    async fn carry_over_progress(&self, from_id: i64, to_id: i64, tx: &mut SqliteConnection) -> KnowledgeResult<bool> {
+        let copied = sqlx::query("
+            UPDATE words
+            SET repitition = old.repitition,
+                e_factor = old.e_factor,
+                review_duration = old.review_duration,
+                next_review_at = old.next_review_at,
+                reviewed = old.reviewed,
+                date_first_reviewed = old.date_first_reviewed
+            FROM (SELECT * FROM words WHERE id = ?) AS old
+            WHERE words.id = ?
+                AND words.reviewed = FALSE")
+            .bind(from_id)
+            .bind(to_id)
+            .execute(&mut *tx).await?
+            .rows_affected();
+
+        Ok(copied > 0)
+    }
 
-#[derive(Debug)]
-pub enum KnowledgeError {
-    DatabaseError(sqlx::Error),
-    MigrationError(sqlx::migrate::MigrateError),
-    TokenizeError
-}
+    // Words from before readings were stored keep an empty one until a retokenize splits them up.
+    // Until then a new word with the same spelling picks up their schedule, rather than being new next to the one that's been studied.
+    async fn sync_legacy_progress(&self, word_id: i64, tx: &mut SqliteConnection) -> KnowledgeResult<()> {
+        let legacy_word: Option<i64> = sqlx::query("
+            SELECT legacy.id AS id
+            FROM words
+                INNER JOIN words AS legacy ON legacy.language = words.language AND legacy.text = words.text
+            WHERE words.id = ? AND words.reading != '' AND words.reviewed = FALSE
+                AND legacy.reading = '' AND legacy.reviewed = TRUE
+            ORDER BY legacy.repitition DESC
+            LIMIT 1")
+            .bind(word_id)
+            .fetch_optional(&mut *tx).await?
+            .map(|row| row.try_get("id"))
+            .transpose()?;
 
-impl Display for KnowledgeError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::DatabaseError(e) => write!(f, "Database error! Error: {}", e),
-            Self::MigrationError(e) => write!(f, "Migration error! Error: {}", e),
-            Self::TokenizeError => write!(f, "Error tokenizing sentence!")
+        if let Some(legacy_word) = legacy_word {
+            self.carry_over_progress(legacy_word, word_id, tx).await?;
         }
+
+        Ok(())
     }
-}
 
-impl std::error::Error for KnowledgeError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        match self {
-            Self::DatabaseError(e) => Some(e),
-            Self::MigrationError(e) => Some(e),
-            Self::TokenizeError => None
+    // If the word hasn't been reviewed, but another spelling of it has, pick up that spelling's schedule.
+    async fn sync_lemma_group_progress(&self, word_id: i64, tx: &mut SqliteConnection) -> KnowledgeResult<()> {
+        let reviewed_variant: Option<i64> = sqlx::query("
+            SELECT id FROM words
+            WHERE lemma_group = (SELECT lemma_group FROM words WHERE id = ? AND reviewed = FALSE)
+                AND language = (SELECT language FROM words WHERE id = ?)
+                AND reviewed = TRUE
+            ORDER BY repitition DESC
+            LIMIT 1")
+            .bind(word_id)
+            .bind(word_id)
+            .fetch_optional(&mut *tx).await?
+            .map(|row| row.try_get("id"))
+            .transpose()?;
+
+        if let Some(reviewed_variant) = reviewed_variant {
+            self.carry_over_progress(reviewed_variant, word_id, tx).await?;
         }
+
+        Ok(())
     }
-}
 
-impl From<sqlx::Error> for KnowledgeError {
-    fn from(value: sqlx::Error) -> Self {
-        KnowledgeError::DatabaseError(value)
+    // Remember words the user already knows, marking them as reviewed with a long interval so they aren't treated as new.
+    // Words we don't have yet are marked when they first turn up. Words that have already been reviewed keep their progress.
+    pub async fn mark_known_words(&self, language: Language, words: &[KnownWord], interval_days: i64) -> KnowledgeResult<KnownWordsReport> {
+        let mut tx = self.connection.begin().await?;
+        let mut entries = 0;
+        for word in words {
+            let text = self.config.normalization.normalize(word.text.trim());
+            if text.is_empty() {
+                continue;
+            }
+            let reading = word.reading.as_deref()
+                .map(|reading| to_hiragana(&self.config.normalization.normalize(reading.trim())))
+                .unwrap_or_default();
+
+            let schedule = word.schedule.as_ref();
+            for spelling in self.word_spellings(language, text) {
+                sqlx::query("
+                    INSERT OR REPLACE INTO known_words(language, text, reading, interval_days, repitition, e_factor, review_duration, next_review_at)
+                        VALUES(?, ?, ?, ?, ?, ?, ?, ?)")
+                    .bind(language.code())
+                    .bind(spelling)
+                    .bind(&reading)
+                    .bind(schedule.map_or(interval_days, |schedule| schedule.duration.num_days()))
+                    .bind(schedule.map(|schedule| schedule.repitition))
+                    .bind(schedule.map(|schedule| schedule.e_factor))
+                    .bind(schedule.map(|schedule| schedule.duration.num_seconds()))
+                    .bind(schedule.map(|schedule| schedule.next_review_at.to_rfc3339()))
+                    .execute(&mut *tx).await?;
+            }
+            entries += 1;
+        }
+
+        let word_ids: Vec<i64> = sqlx::query("
+            SELECT words.id AS id
+            FROM words
+                INNER JOIN known_words ON known_words.language = words.language AND known_words.text = words.text
+                    AND (known_words.reading = '' OR known_words.reading = words.reading)
+            WHERE words.reviewed = FALSE AND words.language = ?")
+            .bind(language.code())
+            .fetch_all(&mut *tx).await?
+            .iter()
+            .map(|row| row.try_get("id"))
+            .collect::<Result<_, _>>()?;
+
+        let mut words_marked = 0;
+        for word_id in word_ids {
+            if self.apply_known_word(word_id, &mut tx).await? {
+                words_marked += 1;
+            }
+        }
+        tx.commit().await?;
+
+        log::info!("Marked {} words as known from a list of {}", words_marked, entries);
+        Ok(KnownWordsReport { entries, words_marked })
     }
-}
 
-impl From<sqlx::migrate::MigrateError> for KnowledgeError {
-    fn from(value: sqlx::migrate::MigrateError) -> Self {
-        KnowledgeError::MigrationError(value)
+    // The ways a word from outside (e.g. a list) could be stored as, it's written however the user likes but the tokenizer
+    // knows how we store it (e.g. lowercased or in its dictionary form).
+    fn word_spellings(&self, language: Language, text: String) -> Vec<String> {
+        let mut spellings = vec![text];
+        if let Ok(morphemes) = self.tokenize(language, &spellings[0]) {
+            if let [morpheme] = &morphemes[..] {
+                spellings.push(self.word_key(morpheme).text);
+            }
+        }
+        spellings.dedup();
+        spellings
     }
-}
 
-pub type KnowledgeResult<T> = Result<T, KnowledgeError>;
+    // Remember words the user wants to learn before other new words, e.g. ones they looked up while reading.
+    // Words we don't have yet are flagged when they first turn up.
+    pub async fn mark_priority_words(&self, language: Language, words: &[String]) -> KnowledgeResult<()> {
+        let mut tx = self.connection.begin().await?;
+        for word in words {
+            let text = self.config.normalization.normalize(word.trim());
+            if text.is_empty() {
+                continue;
+            }
 
-#[derive(Clone)]
-pub struct Knowledge {
-    word_freq: WordFrequencyList,
-    connection: Pool<Sqlite>
-}
+            for spelling in self.word_spellings(language, text) {
+                sqlx::query("INSERT OR IGNORE INTO priority_words(language, text) VALUES(?, ?)")
+                    .bind(language.code())
+                    .bind(spelling)
+                    .execute(&mut *tx).await?;
+            }
+        }
 
-impl Knowledge {
-    pub async fn new() -> Result<Self, KnowledgeError> {
-        // Create the database.
-        let connection = SqlitePoolOptions::new()
-            .connect_with(SqliteConnectOptions::from_str("db.sqlite").unwrap() // TODO: error handling
-                .create_if_missing(true)
-            )
-            .await?;
+        let flagged = sqlx::query("
+            UPDATE words
+            SET priority = TRUE
+            WHERE language = ? AND priority = FALSE
+                AND text IN (SELECT text FROM priority_words WHERE priority_words.language = words.language)")
+            .bind(language.code())
+            .execute(&mut *tx).await?
+            .rows_affected();
+        tx.commit().await?;
 
-        // Run migrations.
-        sqlx::migrate!().run(&connection).await?;
+        log::info!("Flagged {} words we already had to learn first", flagged);
+        Ok(())
+    }
 
-        Ok(Self {
-            word_freq: WordFrequencyList::new(),
-            connection
-        })
+    // Mark a word as known if it's on one of the user's known word lists and hasn't been reviewed yet.
+    // Returns whether it was.
+    async fn apply_known_word(&self, word_id: i64, tx: &mut SqliteConnection) -> KnowledgeResult<bool> {
+        let known_word = sqlx::query("
+            SELECT known_words.interval_days, known_words.repitition, known_words.e_factor,
+                known_words.review_duration, known_words.next_review_at
+            FROM words
+                INNER JOIN known_words ON known_words.language = words.language AND known_words.text = words.text
+                    AND (known_words.reading = '' OR known_words.reading = words.reading)
+            WHERE words.id = ? AND words.reviewed = FALSE
+            ORDER BY COALESCE(known_words.review_duration, known_words.interval_days * 86400) DESC
+            LIMIT 1")
+            .bind(word_id)
+            .fetch_optional(&mut *tx).await?;
+        let Some(known_word) = known_word else {
+            return Ok(false);
+        };
+
+        // Words from a list are treated as if they'd been reviewed a few times already, so the interval keeps growing rather than starting over.
+        // Words from another app carry on with the schedule they had there.
+        let now_time = Local::now().fixed_offset();
+        let interval_days: i64 = known_word.try_get("interval_days")?;
+        let duration = known_word.try_get::<Option<i64>, _>("review_duration")?
+            .map_or(Duration::days(interval_days), Duration::seconds);
+        let next_review_at = known_word.try_get::<Option<String>, _>("next_review_at")?
+            .unwrap_or_else(|| (now_time + duration).to_rfc3339());
+        sqlx::query("
+            UPDATE words
+            SET repitition = ?,
+                e_factor = ?,
+                review_duration = ?,
+                next_review_at = ?,
+                reviewed = TRUE,
+                date_first_reviewed = COALESCE(date_first_reviewed, ?)
+            WHERE id = ?")
+            .bind(known_word.try_get::<Option<i64>, _>("repitition")?.unwrap_or(3))
+            .bind(known_word.try_get::<Option<f64>, _>("e_factor")?.unwrap_or(SuperMemoItem::default().e_factor))
+            .bind(duration.num_seconds())
+            .bind(next_review_at)
+            .bind(now_time.to_rfc3339())
+            .bind(word_id)
+            .execute(&mut *tx).await?;
+
+        Ok(true)
     }
-    
-    fn tokenize_sentence_jumanpp(&self, sentence: &str) -> KnowledgeResult<Vec<String>> {
-        let mut jumanpp = Command::new("jumanpp") // TEMP!!
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn().unwrap(); // TODO: Erro handling!
-
-        if let Some(stdin) = jumanpp.stdin.as_mut().take() {
-            stdin.write_all(sentence.as_bytes()).unwrap(); // TODO: error handling!
-        } 
-
-        match jumanpp.wait_with_output() {
-            Ok(output) => {
-                let data = String::from_utf8(output.stdout).unwrap(); // TODO: handle errors
-                let mut words = Vec::new();
-
-                // Parse the output and find the de-conjugated words.
-                // Each line is a word (in order).
-                // https://github.com/ku-nlp/jumanpp/blob/master/docs/output.md 
-                // The third entry on each line is the dictionary form. That's what we want.
-                // If a line start's with a '@' then that is an alias and we should maybe ignore
-                // that and only take one version of the word.
-                if output.status.success() {
-                    for line in data.lines() {
-                        // Ignore lines that start with '@', these specify aliases (for words that have exactly the same spelling.)
-                        // For now we'll just ignore these, there's no way for us to know which alias is the 'correct' one so just pick
-                        // the first one (most common).
-                        if line.starts_with('@') {
-                            continue;
-                        }
-
-                        // Split the line by spaces
-                        let parts: Vec<&str> = line.split(" ").collect();
-
-                        // Not exactly the best way to do this, but...
-                        // There *should* be 12 space-separated fields, so expect that:
-                        // Note: (this is <= 12 because the last field can sometimes be a quoted string that can contain spaces
-                        // rather than actually parse this, bodge it by just expecting at least 12 fields. We aren't interested
-                        // in the last fields anyway, so it's probably fine.) It might be a good idea to look
-                        // at doing this properly at some point though. Maybe when I go through and sort out all of the error handling.
-                        if parts.len() >= 12 {
-                            let dictionary_form = parts[2];
-                            let word_type = parts[3];
-
-                            // Okay, so for some reason '\␣' is used to refer to a space.
-                            // We uh don't want to include these.
-                            if dictionary_form == r"\␣" {
-                                continue;
-                            }
-
-                            words.push(dictionary_form.to_string());
-                        }
-                    }
-                }
 
-                Ok(words)
-            },
-            Err(e) => {
-                // There was an error, maybe something wrong with the sentence, jumanpp wasn't installed.
-                log::error!("Error calling jumanpp: {}", e);
-                panic!(); // Just panic for now >.<
-            }
+    // Add variant spellings in a language to the user's variant table from a file with a variant and the
+    // representative form it belongs to on each line separated by a tab, e.g. "解る	分かる/わかる"
+    pub async fn load_variants(&self, language: Language, path: &Path) -> KnowledgeResult<()> {
+        let contents = std::fs::read_to_string(path)?;
+
+        let mut tx = self.connection.begin().await?;
+        let mut count = 0;
+        for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let Some((variant, representative)) = line.split_once('\t') else {
+                log::warn!("Ignoring variant line without a tab: {}", line);
+                continue;
+            };
+
+            sqlx::query("
+                INSERT INTO word_variants(language, variant, representative) VALUES(?, ?, ?)
+                ON CONFLICT(language, variant) DO UPDATE SET representative = excluded.representative")
+                .bind(language.code())
+                .bind(self.config.normalization.normalize(variant.trim()))
+                .bind(self.config.normalization.normalize(representative.trim()))
+                .execute(&mut *tx).await?;
+            count += 1;
         }
-    } 
+        tx.commit().await?;
 
-    pub async fn retokenize(&mut self) -> KnowledgeResult<()> {
-        log::info!("Retokenizing sentences...");
+        log::info!("Loaded {} word variants", count);
+        self.apply_variant_groups().await
+    }
 
-        // First open a transaction.
+    // Put words into the groups from the user's variant table and make sure every spelling in a group shares its schedule.
+    async fn apply_variant_groups(&self) -> KnowledgeResult<()> {
         let mut tx = self.connection.begin().await?;
 
-        // Now clear out the word_sentence table.
-        log::info!("Clearing out word_sentence relationships...");
-        sqlx::query("DELETE FROM word_sentence")
-            .execute(&mut *tx).await?; // TODO: handle errors
-
-        // Set the word tables count to 0 for everything
-        log::info!("Setting all words count to 0...");
-        sqlx::query("UPDATE words SET count = 0")
-            .execute(&mut *tx).await?; // TODO: handle errors
+        sqlx::query("
+            UPDATE words
+            SET lemma_group = word_variants.representative
+            FROM word_variants
+            WHERE words.text = word_variants.variant AND words.language = word_variants.language")
+            .execute(&mut *tx).await?;
 
-        // Now go through each sentence and re-tokenize it
-        log::info!("Iterating through all sentences and retokenizing...");
-        let mut sentences_to_process = Vec::new();
+        let mut unsynced = Vec::new();
         {
-            let mut sentences_stream = sqlx::query("SELECT id, text, source FROM sentences")
+            let mut rows = sqlx::query("
+                SELECT id FROM words AS unreviewed
+                WHERE reviewed = FALSE
+                    AND EXISTS (SELECT 1 FROM words WHERE lemma_group = unreviewed.lemma_group AND language = unreviewed.language AND reviewed = TRUE)")
                 .fetch(&mut *tx);
 
-            while let Some(row) = sentences_stream.try_next().await? { // TODO: error handling
-                let sentence: String = row.try_get("text")?;
-                let id: i64 = row.try_get("id")?;
-
-                sentences_to_process.push((id, sentence));
+            while let Some(row) = rows.try_next().await? {
+                unsynced.push(row.try_get::<i64, _>("id")?);
             }
         }
 
-        // Now the stream is closed...
-        for (id, text) in sentences_to_process {
-            // Tokenize
-            let words = self.tokenize_sentence_jumanpp(text.as_str())?;
-
-            // Re-add the sentences
-            self.add_words_to_sentence(id, words, &mut *tx).await?;
+        for word_id in unsynced {
+            self.sync_lemma_group_progress(word_id, &mut tx).await?;
         }
 
         tx.commit().await?;
 
-        log::info!("Finished re-tokenizing");
+        Ok(())
+    }
+
+    // Undo add_words_to_sentence, ready for the sentence to be tokenized again.
+    async fn remove_words_from_sentence(&self, id: i64, tx: &mut SqliteConnection) -> KnowledgeResult<()> {
+        // Words are counted once for every time they appear in a sentence, older sentences don't have tokens
+        // to tell us how many times that was so assume it was once.
+        sqlx::query("
+            UPDATE words
+            SET count = MAX(count - MAX((SELECT COUNT(*) FROM sentence_tokens WHERE sentence_id = ? AND word_id = words.id), 1), 0)
+            WHERE id IN (SELECT word_id FROM word_sentence WHERE sentence_id = ?)")
+            .bind(id)
+            .bind(id)
+            .execute(&mut *tx).await?;
+
+        sqlx::query("DELETE FROM word_sentence WHERE sentence_id = ?")
+            .bind(id)
+            .execute(&mut *tx).await?;
+
+        sqlx::query("DELETE FROM sentence_tokens WHERE sentence_id = ?")
+            .bind(id)
+            .execute(&mut *tx).await?;
+
+        Ok(())
+    }
+
+    // Words that were added before we kept track of readings and parts of speech have an empty reading.
+    // After a retokenize these have been replaced by words with a proper reading, so hand any progress on
+    // the old word over to every new word with the same spelling and get rid of the old word.
+    // This isn't perfect, every reading of a homograph ends up with the same schedule, but that's no worse than before.
+    async fn split_legacy_words(&self, tx: &mut SqliteConnection) -> KnowledgeResult<()> {
+        log::info!("Splitting words without readings...");
+
+        sqlx::query("
+            UPDATE words
+            SET repitition = legacy.repitition,
+                e_factor = legacy.e_factor,
+                review_duration = legacy.review_duration,
+                next_review_at = legacy.next_review_at,
+                reviewed = legacy.reviewed,
+                date_first_reviewed = legacy.date_first_reviewed
+            FROM (SELECT * FROM words WHERE reading = '' AND reviewed = TRUE) AS legacy
+            WHERE words.language = legacy.language
+                AND words.text = legacy.text
+                AND words.reading != ''
+                AND words.reviewed = FALSE")
+            .execute(&mut *tx).await?;
+
+        let deleted = sqlx::query("
+            DELETE FROM words
+            WHERE reading = ''
+                AND NOT EXISTS (SELECT 1 FROM word_sentence WHERE word_id = words.id)")
+            .execute(&mut *tx).await?
+            .rows_affected();
+        log::info!("Split {} words", deleted);
 
-        // Done!
         Ok(())
     }
 
@@ -321,34 +1794,36 @@ impl Knowledge {
         }.unwrap() // TODO: error handling.
     }
 
-    // Get a vector containing a tuple of word id and word text for all the words in a sentence.
-    async fn get_words_in_sentence(&self, sentence_id: i64) -> KnowledgeResult<Vec<(i64, String)>> {
+    // Get all the words in a sentence.
+    async fn get_words_in_sentence(&self, sentence_id: i64) -> KnowledgeResult<Vec<WordData>> {
         let mut words = sqlx::query("
-            SELECT word_id, sentence_id, words.text as word_text
+            SELECT word_id, sentence_id, words.text as word_text, words.reading as word_reading, words.lemma_group as word_lemma_group
             FROM word_sentence
                 INNER JOIN words ON words.id = word_id
-            WHERE sentence_id = ?")
+            WHERE sentence_id = ?
+                AND excluded = FALSE")
             .bind(sentence_id)
             .fetch(&self.connection);
 
         let mut word_vec = Vec::new();
         while let Some(word_row) = words.try_next().await? { // TODO: error handling.
-            word_vec.push((word_row.try_get("word_id")?, word_row.try_get("word_text")?));
+            word_vec.push(WordData::from_row(&word_row)?);
         }
 
         Ok(word_vec)
     }
 
-    async fn get_words_in_sentence_that_need_reviewing(&self, sentence_id: i64) -> KnowledgeResult<Vec<(i64, String)>> {
+    async fn get_words_in_sentence_that_need_reviewing(&self, sentence_id: i64) -> KnowledgeResult<Vec<WordData>> {
         // First bit of useful info is how many reviews there are for today.
         let end_of_day_time = self.get_end_of_day_time();
         let now_time = Local::now().fixed_offset();
 
         let mut words = sqlx::query("
-            SELECT word_id, sentence_id, words.text as word_text, words.next_review_at
+            SELECT word_id, sentence_id, words.text as word_text, words.reading as word_reading, words.lemma_group as word_lemma_group, words.next_review_at
             FROM word_sentence
                 INNER JOIN words ON words.id = word_id
             WHERE sentence_id = ?
+                AND excluded = FALSE
                 AND (
                     reviewed = TRUE
                     AND datetime(next_review_at) < datetime(?) AND review_duration >= 86400
@@ -361,31 +1836,94 @@ impl Knowledge {
 
         let mut word_vec = Vec::new();
         while let Some(word_row) = words.try_next().await? { // TODO: error handling.
-            word_vec.push((word_row.try_get("word_id")?, word_row.try_get("word_text")?));
+            word_vec.push(WordData::from_row(&word_row)?);
         }
 
         Ok(word_vec)
     }
 
-    async fn get_words_in_sentence_that_are_new(&self, sentence_id: i64) -> KnowledgeResult<Vec<(i64, String)>> {
+    async fn get_words_in_sentence_that_are_new(&self, sentence_id: i64) -> KnowledgeResult<Vec<WordData>> {
         let mut words = sqlx::query("
-            SELECT word_id, sentence_id, words.text as word_text, words.next_review_at
+            SELECT word_id, sentence_id, words.text as word_text, words.reading as word_reading, words.lemma_group as word_lemma_group, words.next_review_at
             FROM word_sentence
                 INNER JOIN words ON words.id = word_id
             WHERE sentence_id = ?
+                AND excluded = FALSE
                 AND reviewed = FALSE")
             .bind(sentence_id)
             .fetch(&self.connection);
 
         let mut word_vec = Vec::new();
         while let Some(word_row) = words.try_next().await? { // TODO: error handling.
-            word_vec.push((word_row.try_get("word_id")?, word_row.try_get("word_text")?));
+            word_vec.push(WordData::from_row(&word_row)?);
         }
 
         Ok(word_vec)
     }
 
-    pub async fn get_next_sentence_i_plus_one(&self) -> KnowledgeResult<IPlusOneSentenceData> {
+    // Get the sentences either side of a sentence in the text it was added from.
+    // If it was added from more than one text the first one is used. Sentences added before texts were kept track of don't have any.
+    pub async fn get_sentence_context(&self, sentence_id: i64, count: i64) -> KnowledgeResult<SentenceContext> {
+        let mut context = SentenceContext::default();
+
+        let Some(row) = sqlx::query("SELECT document_id, position FROM document_sentences WHERE sentence_id = ? ORDER BY document_id, position LIMIT 1")
+            .bind(sentence_id)
+            .fetch_optional(&self.connection).await? else {
+            return Ok(context);
+        };
+        let document_id: i64 = row.try_get("document_id")?;
+        let position: i64 = row.try_get("position")?;
+
+        let mut rows = sqlx::query("
+            SELECT position, sentences.text AS text
+            FROM document_sentences
+                INNER JOIN sentences ON sentences.id = sentence_id
+            WHERE document_id = ? AND position BETWEEN ? AND ? AND position != ?
+            ORDER BY position")
+            .bind(document_id)
+            .bind(position - count)
+            .bind(position + count)
+            .bind(position)
+            .fetch(&self.connection);
+
+        while let Some(row) = rows.try_next().await? {
+            let text: String = row.try_get("text")?;
+            match row.try_get::<i64, _>("position")? < position {
+                true => context.before.push(text),
+                false => context.after.push(text)
+            }
+        }
+
+        Ok(context)
+    }
+
+    // Get the tokens that make up a sentence in order.
+    // Sentences that haven't been tokenized since we started storing tokens won't have any.
+    pub async fn get_tokens_in_sentence(&self, sentence_id: i64) -> KnowledgeResult<Vec<TokenData>> {
+        let mut tokens = sqlx::query("
+            SELECT sentence_tokens.reading AS reading, start_offset, end_offset, word_id, COALESCE(words.excluded, TRUE) AS excluded
+            FROM sentence_tokens
+                LEFT JOIN words ON words.id = word_id
+            WHERE sentence_id = ?
+            ORDER BY position")
+            .bind(sentence_id)
+            .fetch(&self.connection);
+
+        let mut token_vec = Vec::new();
+        while let Some(token_row) = tokens.try_next().await? {
+            token_vec.push(TokenData {
+                reading: token_row.try_get("reading")?,
+                offset: token_row.try_get::<i64, _>("start_offset")? as usize,
+                end_offset: token_row.try_get::<i64, _>("end_offset")? as usize,
+                word_id: token_row.try_get("word_id")?,
+                excluded: token_row.try_get("excluded")?
+            });
+        }
+
+        Ok(token_vec)
+    }
+
+    pub async fn get_next_sentence_i_plus_one(&self, language: Language) -> KnowledgeResult<IPlusOneSentenceData> {
         let end_of_day_time = self.get_end_of_day_time();
         let now_time = Local::now().fixed_offset();
 
@@ -399,13 +1937,16 @@ impl Knowledge {
         match sqlx::query("
             SELECT 
                 word_id, sentence_id, 
-                sentences.text AS sentence_text, sentences.id, sentences.source,
+                sentences.text AS sentence_text, sentences.id, sentences.source, sentences.start_time, sentences.end_time, sentences.translation,
                 words.next_review_at as review_at, words.reviewed AS reviewed, 
                 SUM(CASE WHEN datetime(words.next_review_at) < datetime(?) AND review_duration >= 86400 OR datetime(words.next_review_at) < datetime(?) THEN 1 ELSE 0 END) as words_that_need_reviewing,
                 SUM(CASE WHEN words.reviewed = FALSE THEN 1 ELSE 0 END) as words_that_are_new
             FROM word_sentence
                 INNER JOIN sentences ON sentences.id = sentence_id
                 INNER JOIN words ON words.id = word_id
+            WHERE
+                words.excluded = FALSE
+                AND sentences.language = ?
             GROUP BY
                 sentence_id
             HAVING
@@ -418,6 +1959,7 @@ impl Knowledge {
             ")
             .bind(end_of_day_time.to_rfc3339())
             .bind(now_time.to_rfc3339())
+            .bind(language.code())
             .fetch_one(&self.connection)
             .await {
                 
@@ -435,11 +1977,17 @@ impl Knowledge {
                     let words_being_reviewed = self.get_words_in_sentence_that_need_reviewing(sentence_id).await?;
                     let words_that_are_new = self.get_words_in_sentence_that_are_new(sentence_id).await?;
                     let sentence_source = row.try_get("source")?;
+                    let sentence_timing = SentenceTiming::from_row(&row)?;
+                    let sentence_translation = row.try_get("translation")?;
+                    let tokens = self.get_tokens_in_sentence(sentence_id).await?;
 
                     return Ok(IPlusOneSentenceData {
                         sentence_id,
                         sentence_text,
                         sentence_source,
+                        sentence_timing,
+                        sentence_translation,
+                        tokens,
                         words_being_reviewed,
                         words_that_are_new
                     });
@@ -457,25 +2005,32 @@ impl Knowledge {
 
         // Okay so there aren't any sentences that contain words that we need to review. 
         // Let's look for sentences that contain the least amount of new information so that we can learn new words.
+        // Of those, ones with words the user has asked to learn first (e.g. ones they looked up) come before the rest.
         match sqlx::query("
             SELECT 
                 word_id, sentence_id, 
-                sentences.text AS sentence_text, sentences.id, sentences.source,
+                sentences.text AS sentence_text, sentences.id, sentences.source, sentences.start_time, sentences.end_time, sentences.translation,
                 words.reviewed as word_reviewed, 
                 SUM(CASE WHEN words.reviewed = FALSE THEN 1 ELSE 0 END) as words_that_are_new,
+                SUM(CASE WHEN words.reviewed = FALSE AND words.priority = TRUE THEN 1 ELSE 0 END) as priority_words_that_are_new,
                 AVG(CASE WHEN words.reviewed = FALSE THEN words.count ELSE NULL END) as average_new_word_count
             FROM word_sentence
                 INNER JOIN sentences ON sentences.id = sentence_id
                 INNER JOIN words ON words.id = word_id
+            WHERE
+                words.excluded = FALSE
+                AND sentences.language = ?
             GROUP BY
                 sentence_id
             HAVING
                 words_that_are_new > 0
             ORDER by
                 words_that_are_new ASC,
+                priority_words_that_are_new > 0 DESC,
                 average_new_word_count DESC,
                 random()
             LIMIT 1")
+            .bind(language.code())
             .fetch_one(&self.connection)
             .await {
 
@@ -489,11 +2044,17 @@ impl Knowledge {
                 let words_being_reviewed = self.get_words_in_sentence_that_need_reviewing(sentence_id).await?;
                 let words_that_are_new = self.get_words_in_sentence_that_are_new(sentence_id).await?;
                 let sentence_source = row.try_get("source")?;
+                let sentence_timing = SentenceTiming::from_row(&row)?;
+                let sentence_translation = row.try_get("translation")?;
+                let tokens = self.get_tokens_in_sentence(sentence_id).await?;
 
                 Ok(IPlusOneSentenceData {
                     sentence_id,
                     sentence_text,
                     sentence_source,
+                    sentence_timing,
+                    sentence_translation,
+                    tokens,
                     words_being_reviewed,
                     words_that_are_new
                 })
@@ -509,8 +2070,11 @@ impl Knowledge {
                     sentence_id: 0,
                     sentence_text: "No sentence with any new words and no words are scheduled for reviewing.".to_string(),
                     sentence_source: "".to_string(),
-                    words_being_reviewed: vec![(0, "".to_string())],
-                    words_that_are_new: vec![(0, "".to_string())]
+                    sentence_timing: None,
+                    sentence_translation: None,
+                    tokens: Vec::new(),
+                    words_being_reviewed: Vec::new(),
+                    words_that_are_new: Vec::new()
                 })
             },
 
@@ -524,23 +2088,26 @@ impl Knowledge {
     pub async fn review_sentence(&self, sentence_id: i64, response_quality: f64) -> KnowledgeResult<()> {
         // Find all the words in the sentence and then review them all!
         let words = self.get_words_in_sentence(sentence_id).await?;
-        for (word_id, word_text) in words {
-            self.review_word(word_id, response_quality).await?;
+        for word in words {
+            self.review_word(word.id, response_quality).await?;
         }
 
         Ok(())
     }
 
-    pub async fn get_review_info(&self) -> KnowledgeResult<ReviewInfoData> {
+    pub async fn get_review_info(&self, language: Language) -> KnowledgeResult<ReviewInfoData> {
         // First bit of useful info is how many reviews there are for today.
         let end_of_day_time = self.get_end_of_day_time();
         let now_time = Local::now().fixed_offset();
 
         let review_count: i64 = sqlx::query("
-            SELECT COUNT(*) FROM words
-            WHERE reviewed = TRUE
-                AND datetime(next_review_at) < datetime(?) AND review_duration >= 86400
-                OR datetime(next_review_at) < datetime(?)")
+            SELECT COUNT(DISTINCT COALESCE(lemma_group, CAST(id AS TEXT))) FROM words
+            WHERE excluded = FALSE
+                AND language = ?
+                AND (reviewed = TRUE
+                    AND datetime(next_review_at) < datetime(?) AND review_duration >= 86400
+                    OR datetime(next_review_at) < datetime(?))")
+            .bind(language.code())
             .bind(end_of_day_time.to_rfc3339())
             .bind(now_time.to_rfc3339())
             .fetch_one(&self.connection).await.unwrap() // TODO: error handling.
@@ -590,7 +2157,7 @@ impl Knowledge {
 
                 info!("Reviewing word id {}, updated review data: {:?}", review_word_id, &sm);
 
-                // Store it.
+                // Store it, other spellings of the same word share the same schedule.
                 {
                     let mut tx = self.connection.begin().await?;
                     sqlx::query("
@@ -602,13 +2169,17 @@ impl Knowledge {
                             reviewed = TRUE,
                             date_first_reviewed = CASE WHEN date_first_reviewed IS NULL THEN ? ELSE date_first_reviewed END
                         WHERE 
-                            id = ?")
+                            id = ?
+                            OR lemma_group = (SELECT lemma_group FROM words WHERE id = ?)
+                                AND language = (SELECT language FROM words WHERE id = ?)")
                         .bind(sm.repitition)
                         .bind(sm.e_factor)
                         .bind(sm.duration.num_seconds())
                         .bind(next_review_at)
                         .bind(now_time.to_rfc3339())
                         .bind(review_word_id)
+                        .bind(review_word_id)
+                        .bind(review_word_id)
                         .execute(&mut *tx).await?;
 
                     tx.commit().await?;
@@ -629,92 +2200,294 @@ impl Knowledge {
         }
     }
 
-    async fn add_sentence(&mut self, sentence: &str, source: &str) -> KnowledgeResult<()> {
-        info!("Adding sentence {} from source {}", sentence, source);
+    // Returns the sentence's id, whether it's new or was already there.
+    async fn add_sentence(&mut self, sentence: &str, source: &str, language: Language, timing: Option<SentenceTiming>,
+                          hints: &[ReadingHint]) -> KnowledgeResult<i64> {
+        info!("Adding {} sentence {} from source {}", language.name(), sentence, source);
 
         // Get the current datetime
         let now_time = Local::now().fixed_offset();
 
         // Tokenize the sentence to get the words.
-        let words = self.tokenize_sentence_jumanpp(sentence)?;
-        log::info!("Contains words: {:?}", words);
+        let words = reading_hint::apply(sentence, self.tokenize(language, sentence)?, hints);
 
         // Start a database transaction.
         let mut tx = self.connection.begin().await?;
 
         // Insert the sentence to the sentences table.
         let sentence_id: Option<i64> = match sqlx::query(
-            "INSERT OR IGNORE INTO sentences(text, date_added, source)
-                    VALUES(?, ?, ?)
+            "INSERT OR IGNORE INTO sentences(text, date_added, source, language, start_time, end_time)
+                    VALUES(?, ?, ?, ?, ?, ?)
                     RETURNING id;")
                 .bind(sentence)
                 .bind(now_time.to_rfc3339())
                 .bind(source)
+                .bind(language.code())
+                .bind(timing.map(|timing| timing.start_ms))
+                .bind(timing.map(|timing| timing.end_ms))
                 .fetch_one(&mut *tx).await {
-                    
-                Err(e) => None,
-                Ok(row) => Some(row.try_get("id").expect("No id in inserted sentence."))
+
+                Err(sqlx::Error::RowNotFound) => None,
+                Err(e) => return Err(KnowledgeError::from(e)),
+                Ok(row) => Some(row.try_get("id")?)
             };
         
         // If the sentence already existed, then we haven't done anything and we don't have a new sentence id.
         // The words will have already been inserted the first time we added the sentence.
-        if let Some(sentence_id) = sentence_id  {
-            self.add_words_to_sentence(sentence_id, words, &mut tx).await?;
-        }
+        let sentence_id = match sentence_id {
+            Some(sentence_id) => {
+                // The hints are kept so the sentence is read the same way when it's tokenized again.
+                for hint in hints {
+                    sqlx::query("INSERT OR IGNORE INTO sentence_reading_hints(sentence_id, start_offset, end_offset, reading) VALUES(?, ?, ?, ?);")
+                        .bind(sentence_id)
+                        .bind(hint.start as i64)
+                        .bind(hint.end as i64)
+                        .bind(&hint.reading)
+                        .execute(&mut *tx).await?;
+                }
+
+                self.add_words_to_sentence(sentence_id, words, &mut tx).await?;
+                sentence_id
+            },
+            None => sqlx::query("SELECT id FROM sentences WHERE text = ?")
+                .bind(sentence)
+                .fetch_one(&mut *tx).await?
+                .try_get("id")?
+        };
 
         // Commit to the transaction.
         tx.commit().await?; // TODO: error handling
 
-        Ok(())
+        Ok(sentence_id)
     }
 
-    async fn add_words_to_sentence(&mut self, id: i64, words: Vec<String>, tx: &mut SqliteConnection) -> KnowledgeResult<()> {
+    async fn add_words_to_sentence(&mut self, id: i64, words: Vec<Morpheme>, tx: &mut SqliteConnection) -> KnowledgeResult<()> {
         let now_time = Local::now().fixed_offset();
 
-        log::info!("Adding words {:?}", words);
+        log::info!("Adding words {:?}", words.iter().map(|morpheme| &morpheme.dictionary_form).collect::<Vec<_>>());
+
+        // Clear out any tokens from a previous tokenization.
+        sqlx::query("DELETE FROM sentence_tokens WHERE sentence_id = ?")
+            .bind(id)
+            .execute(&mut *tx).await?;
+
+        // Remember what tokenized the sentence.
+        let language = self.get_sentence_language(id, tx).await?;
+        let tokenizer = self.tokenizer(language)?;
+        sqlx::query("UPDATE sentences SET tokenizer = ?, tokenizer_version = ? WHERE id = ?")
+            .bind(tokenizer.name())
+            .bind(tokenizer.version())
+            .bind(id)
+            .execute(&mut *tx).await?;
 
         // Let's go over the words.
-        for word in &words {
-            let freq = self.word_freq.get_word_freq(&word);
+        let word_freq = self.word_freq.clone();
+        for (position, morpheme) in words.iter().enumerate() {
+            let key = self.word_key(morpheme);
+            let freq = word_freq.get(&language).map_or(0, |list| list.get_word_freq(&key.text));
+            let excluded = self.config.excluded_pos.contains(&morpheme.part_of_speech());
+
+            // Other spellings of the word are grouped together, the user's variant table takes priority over the tokenizer.
+            let user_lemma_group: Option<String> = sqlx::query("SELECT representative FROM word_variants WHERE language = ? AND variant = ?")
+                .bind(language.code())
+                .bind(&key.text)
+                .fetch_optional(&mut *tx).await?
+                .map(|row| row.try_get("representative"))
+                .transpose()?;
+            let lemma_group = user_lemma_group.or_else(|| morpheme.lemma_group().map(|rep| self.config.normalization.normalize(&rep)));
 
             // Insert into known words, or increment count if we already have it.
             sqlx::query(
-                    "INSERT INTO words(count, frequency, text, date_added)
-                        VALUES(1, ?, ?, ?)
-                        ON CONFLICT(text) DO UPDATE SET count=count + 1;")
+                    "INSERT INTO words(count, frequency, language, text, reading, date_added, pos, excluded, lemma_group)
+                        VALUES(1, ?, ?, ?, ?, ?, ?, ?, ?)
+                        ON CONFLICT(language, text, reading, pos) DO UPDATE SET count=count + 1, excluded=excluded.excluded, lemma_group=excluded.lemma_group;")
                     .bind(freq)
-                    .bind(&word)
+                    .bind(language.code())
+                    .bind(&key.text)
+                    .bind(&key.reading)
                     .bind(now_time.to_rfc3339())
+                    .bind(&key.pos)
+                    .bind(excluded)
+                    .bind(&lemma_group)
                     .execute(&mut *tx).await?;
 
             // Create the word->sentence relationship.
             let word_id: i64 = sqlx::query(
                     "SELECT id, text
                         FROM words
-                        WHERE text = ?")
-                .bind(&word)
+                        WHERE language = ? AND text = ? AND reading = ? AND pos = ?")
+                .bind(language.code())
+                .bind(&key.text)
+                .bind(&key.reading)
+                .bind(&key.pos)
                 .fetch_one(&mut *tx).await?
                 .try_get("id")?;
 
+            self.sync_legacy_progress(word_id, tx).await?;
+            if lemma_group.is_some() {
+                self.sync_lemma_group_progress(word_id, tx).await?;
+            }
+            self.apply_known_word(word_id, tx).await?;
+            // And flag it if it's one the user wants to learn first.
+            sqlx::query("
+                UPDATE words
+                SET priority = TRUE
+                WHERE id = ? AND EXISTS(SELECT 1 FROM priority_words WHERE priority_words.language = words.language AND priority_words.text = words.text)")
+                .bind(word_id)
+                .execute(&mut *tx).await?;
+
             sqlx::query(
                     "INSERT OR IGNORE INTO word_sentence(word_id, sentence_id)
                         VALUES(?, ?);")
                 .bind(word_id)
                 .bind(id)
                 .execute(&mut *tx).await?;
+
+            // Remember where the word appeared in the sentence and how it was read there.
+            sqlx::query(
+                    "INSERT INTO sentence_tokens(sentence_id, position, surface, reading, start_offset, end_offset, word_id)
+                        VALUES(?, ?, ?, ?, ?, ?, ?);")
+                .bind(id)
+                .bind(position as i64)
+                .bind(&morpheme.surface)
+                .bind(to_hiragana(&morpheme.reading))
+                .bind(morpheme.offset as i64)
+                .bind((morpheme.offset + morpheme.surface.chars().count()) as i64)
+                .bind(word_id)
+                .execute(&mut *tx).await?;
         }
 
         Ok(())
     }
 
-    pub async fn add_text(&mut self, text: &str, source: &str) -> KnowledgeResult<i64> {
-        let sentences = iterate_sentences(text);
-        let sentences_count = sentences.len();
-        for sentence in sentences {
-            // Split the sentence into words and add that to the database.
-            self.add_sentence(sentence.as_str(), source).await?;
+    pub async fn add_text(&mut self, text: &str, source: &str, language: Language) -> KnowledgeResult<AddTextReport> {
+        self.add_document(&[DocumentPart { text: text.to_string(), timing: None, ruby: Vec::new() }], source, language).await
+    }
+
+    // Add a single sentence along with a translation of it, e.g. from a bilingual corpus.
+    // Sentences that are already there are skipped, though they're given the translation if they don't have one.
+    // Returns whether the sentence was added.
+    pub async fn add_translated_sentence(&mut self, sentence: &str, translation: &str, source: &str, language: Language) -> KnowledgeResult<bool> {
+        let sentence = self.config.normalization.normalize(sentence.trim());
+        if sentence.is_empty() {
+            return Ok(false);
+        }
+
+        let existing = sqlx::query("UPDATE sentences SET translation = COALESCE(translation, ?) WHERE text = ? RETURNING id")
+            .bind(translation)
+            .bind(&sentence)
+            .fetch_optional(&self.connection).await?;
+        if existing.is_some() {
+            return Ok(false);
+        }
+
+        let sentence_id = self.add_sentence(&sentence, source, language, None, &[]).await?;
+        sqlx::query("UPDATE sentences SET translation = ? WHERE id = ?")
+            .bind(translation)
+            .bind(sentence_id)
+            .execute(&self.connection).await?;
+
+        Ok(true)
+    }
+
+    // How text in a language is split up into sentences.
+    pub fn segmenter(&self, language: Language) -> Segmenter {
+        Segmenter::new(language, &self.config.segmenter)
+    }
+
+    // Add the sentences of a document made up of several parts, like the cues of a subtitle file.
+    pub async fn add_document(&mut self, parts: &[DocumentPart], source: &str, language: Language) -> KnowledgeResult<AddTextReport> {
+        let segmenter = self.segmenter(language);
+
+        let mut report = AddTextReport::default();
+        let mut sentence_ids = Vec::new();
+        for part in parts {
+            let text = self.config.normalization.normalize(&part.text);
+            let segmentation = segmenter.segment(&text);
+            for rejected in &segmentation.rejected {
+                info!("Not adding sentence {}", rejected);
+            }
+
+            let mut ruby: Vec<Ruby> = part.ruby.iter()
+                .map(|ruby| Ruby {
+                    base: self.config.normalization.normalize(&ruby.base),
+                    reading: to_hiragana(&self.config.normalization.normalize(&ruby.reading))
+                })
+                .collect();
+
+            for sentence in segmentation.sentences {
+                // Split the sentence into words and add that to the database.
+                let hints = reading_hint::locate(&sentence, &mut ruby);
+                sentence_ids.push(self.add_sentence(sentence.as_str(), source, language, part.timing, &hints).await?);
+                report.sentences_added += 1;
+            }
+            report.sentences_rejected.extend(segmentation.rejected);
+        }
+
+        // Keep track of the order of the sentences so they can be shown in context later.
+        // A document without any sentences in it has nothing to show.
+        if sentence_ids.is_empty() {
+            return Ok(report);
+        }
+
+        let mut tx = self.connection.begin().await?;
+        let document_id: i64 = sqlx::query("INSERT INTO documents(source, language, date_added) VALUES(?, ?, ?) RETURNING id;")
+            .bind(source)
+            .bind(language.code())
+            .bind(Local::now().fixed_offset().to_rfc3339())
+            .fetch_one(&mut *tx).await?
+            .try_get("id")?;
+
+        for (position, sentence_id) in sentence_ids.into_iter().enumerate() {
+            sqlx::query("INSERT INTO document_sentences(document_id, position, sentence_id) VALUES(?, ?, ?);")
+                .bind(document_id)
+                .bind(position as i64)
+                .bind(sentence_id)
+                .execute(&mut *tx).await?;
         }
+        tx.commit().await?;
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remapper_picks_the_word_covering_the_most_of_the_old_one() {
+        let mut remapper = LemmaRemapper::default();
+        // 分かった was one token, the new tokenizer splits it into 分かっ and た.
+        remapper.record(&[(0, 4, 1)], &[(0, 3, 2), (3, 4, 3)]);
+
+        assert_eq!(remapper.best_match(1), Some(2));
+    }
 
-        Ok(sentences_count as i64)
+    #[test]
+    fn remapper_adds_up_votes_across_sentences() {
+        let mut remapper = LemmaRemapper::default();
+        remapper.record(&[(0, 2, 1)], &[(0, 2, 2)]);
+        remapper.record(&[(5, 7, 1)], &[(5, 7, 3)]);
+        remapper.record(&[(1, 3, 1)], &[(1, 3, 3)]);
+
+        assert_eq!(remapper.best_match(1), Some(3));
+    }
+
+    #[test]
+    fn remapper_breaks_ties_with_the_lowest_id() {
+        let mut remapper = LemmaRemapper::default();
+        remapper.record(&[(0, 2, 1)], &[(0, 1, 5), (1, 2, 4)]);
+
+        assert_eq!(remapper.best_match(1), Some(4));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn remapper_ignores_unchanged_and_untouched_words() {
+        let mut remapper = LemmaRemapper::default();
+        remapper.record(&[(0, 2, 1), (2, 4, 2)], &[(0, 2, 1), (4, 6, 3)]);
+
+        assert_eq!(remapper.best_match(1), None);
+        assert_eq!(remapper.best_match(2), None);
+    }
+}
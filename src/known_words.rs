@@ -0,0 +1,287 @@
+use std::path::Path;
+
+use crate::html::HtmlDocument;
+use crate::knowledge::KnownWord;
+
+// Headers people give the word and reading columns of a vocabulary spreadsheet.
+const WORD_HEADERS: [&str; 8] = ["word", "words", "expression", "vocab", "vocabulary", "term", "front", "kanji"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KnownWordsFormat {
+    // A word on each line, optionally followed by a tab and its reading.
+    List,
+    Csv,
+    // Anki's "Notes in Plain Text" export, which has #key:value headers and may have HTML and furigana in its fields.
+    Anki
+}
+
+impl KnownWordsFormat {
+    pub fn detect(path: &Path, contents: &str) -> Self {
+        let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+        if contents.lines().next().is_some_and(|line| line.starts_with("#separator:") || line.starts_with("#html:")) {
+            return Self::Anki;
+        }
+        match extension.as_deref() {
+            Some("csv") => Self::Csv,
+            _ => Self::List
+        }
+    }
+}
+
+// Which columns of a CSV or Anki export to take the words from, counting from 1.
+#[derive(Clone, Copy, Debug)]
+pub struct Columns {
+    pub word: usize,
+    pub reading: Option<usize>
+}
+
+pub fn parse(contents: &str, format: KnownWordsFormat, columns: Columns) -> Vec<KnownWord> {
+    let contents = contents.trim_start_matches('\u{FEFF}');
+    match format {
+        KnownWordsFormat::List => contents.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (text, reading) = line.split_once('\t').unwrap_or((line, ""));
+                KnownWord {
+                    text: text.trim().to_string(),
+                    reading: Some(reading.trim().to_string()).filter(|reading| !reading.is_empty()),
+                    schedule: None
+                }
+            })
+            .collect(),
+        KnownWordsFormat::Csv => {
+            let mut rows = parse_delimited(contents, ',');
+            // Skip the header if there is one.
+            if rows.first()
+                .and_then(|row| row.get(columns.word - 1))
+                .is_some_and(|header| WORD_HEADERS.contains(&header.trim().to_lowercase().as_str())) {
+                rows.remove(0);
+            }
+            rows_to_words(rows, columns, false)
+        },
+        KnownWordsFormat::Anki => {
+            let mut separator = '\t';
+            let mut html = false;
+            let mut body = Vec::new();
+            for line in contents.lines() {
+                match line.strip_prefix('#').and_then(|header| header.split_once(':')) {
+                    Some(("separator", value)) => separator = match value.trim() {
+                        "comma" | "Comma" => ',',
+                        "semicolon" | "Semicolon" => ';',
+                        "space" | "Space" => ' ',
+                        "pipe" | "Pipe" => '|',
+                        "colon" | "Colon" => ':',
+                        _ => '\t'
+                    },
+                    Some(("html", value)) => html = value.trim() == "true",
+                    Some(_) => {},
+                    None => body.push(line)
+                }
+            }
+            rows_to_words(parse_delimited(&body.join("\n"), separator), columns, html)
+        }
+    }
+}
+
+fn rows_to_words(rows: Vec<Vec<String>>, columns: Columns, html: bool) -> Vec<KnownWord> {
+    rows.into_iter().filter_map(|row| {
+        let (text, furigana_reading) = clean_field(row.get(columns.word - 1)?, html);
+        if text.is_empty() {
+            return None;
+        }
+        let reading = columns.reading
+            .and_then(|column| row.get(column - 1))
+            .map(|field| clean_field(field, html).0)
+            .filter(|reading| !reading.is_empty());
+
+        Some(KnownWord { text, reading: reading.or(furigana_reading), schedule: None })
+    }).collect()
+}
+
+// The word in an Anki field and the reading its furigana gives, if it has any.
+// Only the first line is kept, fields often have an example sentence or a note under the word.
+pub fn clean_field(field: &str, html: bool) -> (String, Option<String>) {
+    let field = match html {
+        true => HtmlDocument::parse(field).text().lines().next().unwrap_or_default().to_string(),
+        false => field.trim().to_string()
+    };
+
+    // Audio is put in a field as [sound:file.mp3], which isn't furigana.
+    let mut text = String::new();
+    let mut rest = field.as_str();
+    while let Some(start) = rest.find("[sound:") {
+        text.push_str(&rest[..start]);
+        rest = rest[start..].split_once(']').map_or("", |(_, rest)| rest);
+    }
+    text.push_str(rest);
+
+    // Anki's furigana looks like 食[た]べる, which is the word and its reading at once.
+    split_furigana(text.trim())
+}
+
+// Split Anki furigana like "日本[にほん] 語[ご]" into the text (日本語) and its reading (にほんご), if there's any furigana.
+fn split_furigana(field: &str) -> (String, Option<String>) {
+    if !field.contains('[') {
+        return (field.to_string(), None);
+    }
+
+    let mut text = String::new();
+    let mut reading = String::new();
+    // The characters since the last space, which are what the next furigana goes on.
+    let mut base = String::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                let furigana: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                text.push_str(&base);
+                reading.push_str(&furigana);
+                base.clear();
+            },
+            ' ' => {
+                text.push_str(&base);
+                reading.push_str(&base);
+                base.clear();
+            },
+            c => base.push(c)
+        }
+    }
+    text.push_str(&base);
+    reading.push_str(&base);
+
+    (text, Some(reading))
+}
+
+// Split delimited text into rows of fields, with fields that can be quoted like "a, b" and have "" for a quote in them.
+fn parse_delimited(contents: &str, separator: char) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = contents.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, c) if c == separator => row.push(std::mem::take(&mut field)),
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            },
+            (false, '\r') => {},
+            (false, c) => field.push(c)
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows.retain(|row| row.iter().any(|field| !field.trim().is_empty()));
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(contents: &str, format: KnownWordsFormat, columns: Columns) -> Vec<(String, Option<String>)> {
+        parse(contents, format, columns).into_iter().map(|word| (word.text, word.reading)).collect()
+    }
+
+    fn word(text: &str, reading: Option<&str>) -> (String, Option<String>) {
+        (text.to_string(), reading.map(str::to_string))
+    }
+
+    const FIRST: Columns = Columns { word: 1, reading: None };
+
+    #[test]
+    fn parses_delimited_text_with_quotes() {
+        let rows = parse_delimited("a,\"b, c\",\"say \"\"hi\"\"\"\r\n\"multi\nline\",,d\n\n", ',');
+
+        assert_eq!(rows, [
+            vec!["a".to_string(), "b, c".to_string(), "say \"hi\"".to_string()],
+            vec!["multi\nline".to_string(), String::new(), "d".to_string()]
+        ]);
+    }
+
+    #[test]
+    fn a_quote_inside_a_field_is_just_a_quote() {
+        assert_eq!(parse_delimited("12\" pizza,b", ','), [vec!["12\" pizza".to_string(), "b".to_string()]]);
+    }
+
+    #[test]
+    fn the_last_row_doesnt_need_a_newline() {
+        assert_eq!(parse_delimited("a\tb", '\t'), [vec!["a".to_string(), "b".to_string()]]);
+    }
+
+    #[test]
+    fn splits_furigana() {
+        assert_eq!(split_furigana("日本[にほん] 語[ご]"), ("日本語".to_string(), Some("にほんご".to_string())));
+        assert_eq!(split_furigana("食[た]べる"), ("食べる".to_string(), Some("たべる".to_string())));
+        assert_eq!(split_furigana("お 茶[ちゃ]"), ("お茶".to_string(), Some("おちゃ".to_string())));
+        assert_eq!(split_furigana("猫"), ("猫".to_string(), None));
+    }
+
+    #[test]
+    fn cleans_anki_fields() {
+        assert_eq!(clean_field("猫[ねこ][sound:neko.mp3]", false), ("猫".to_string(), Some("ねこ".to_string())));
+        assert_eq!(clean_field("<b>犬</b><br>A dog.", true), ("犬".to_string(), None));
+        assert_eq!(clean_field("  鳥 ", false), ("鳥".to_string(), None));
+    }
+
+    #[test]
+    fn parses_lists() {
+        let list = "\u{FEFF}# My words\n猫\n犬\tいぬ\n\n  鳥  \n";
+
+        assert_eq!(words(list, KnownWordsFormat::List, FIRST), [word("猫", None), word("犬", Some("いぬ")), word("鳥", None)]);
+    }
+
+    #[test]
+    fn parses_csv_skipping_the_header() {
+        let csv = "Meaning,Word,Reading\ncat,猫,ねこ\ndog,\"犬\",\n";
+        let columns = Columns { word: 2, reading: Some(3) };
+
+        assert_eq!(words(csv, KnownWordsFormat::Csv, columns), [word("猫", Some("ねこ")), word("犬", None)]);
+    }
+
+    #[test]
+    fn csv_without_a_header_keeps_the_first_row() {
+        assert_eq!(words("猫\n犬\n", KnownWordsFormat::Csv, FIRST), [word("猫", None), word("犬", None)]);
+    }
+
+    #[test]
+    fn rows_without_the_column_are_skipped() {
+        let columns = Columns { word: 2, reading: None };
+        assert_eq!(words("a,猫\nb\n", KnownWordsFormat::Csv, columns), [word("猫", None)]);
+    }
+
+    #[test]
+    fn parses_anki_exports() {
+        let export = "#separator:tab\n#html:true\n#notetype column:1\n日本[にほん] 語[ご]\tJapanese\n<div>猫</div>\tcat\n";
+
+        assert_eq!(words(export, KnownWordsFormat::Anki, FIRST), [word("日本語", Some("にほんご")), word("猫", None)]);
+    }
+
+    #[test]
+    fn anki_exports_with_other_separators() {
+        let export = "#separator:Semicolon\n#html:false\n犬;いぬ\n";
+        let columns = Columns { word: 1, reading: Some(2) };
+
+        assert_eq!(words(export, KnownWordsFormat::Anki, columns), [word("犬", Some("いぬ"))]);
+    }
+
+    #[test]
+    fn detects_the_format() {
+        assert_eq!(KnownWordsFormat::detect(Path::new("words.txt"), "#separator:tab\n"), KnownWordsFormat::Anki);
+        assert_eq!(KnownWordsFormat::detect(Path::new("words.CSV"), "猫,ねこ\n"), KnownWordsFormat::Csv);
+        assert_eq!(KnownWordsFormat::detect(Path::new("words.txt"), "猫\n"), KnownWordsFormat::List);
+    }
+}
@@ -0,0 +1,53 @@
+use clap::ValueEnum;
+
+// The languages sentences can be added in. Each has its own way of splitting text into sentences,
+// its own tokenizer and its own frequency list, and is reviewed separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Language {
+    Japanese,
+    Spanish,
+    Korean,
+    English
+}
+
+impl Language {
+    pub const ALL: [Language; 4] = [Self::Japanese, Self::Spanish, Self::Korean, Self::English];
+
+    // The code stored in the database.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Japanese => "ja",
+            Self::Spanish => "es",
+            Self::Korean => "ko",
+            Self::English => "en"
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|language| language.code() == code)
+    }
+
+    // The ISO 639-3 code, which is what Tatoeba uses.
+    pub fn iso_639_3(&self) -> &'static str {
+        match self {
+            Self::Japanese => "jpn",
+            Self::Spanish => "spa",
+            Self::Korean => "kor",
+            Self::English => "eng"
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Japanese => "Japanese",
+            Self::Spanish => "Spanish",
+            Self::Korean => "Korean",
+            Self::English => "English"
+        }
+    }
+
+    // Whether words are written with spaces between them, so they can be split up without a dictionary.
+    pub fn has_word_spacing(&self) -> bool {
+        !matches!(self, Self::Japanese)
+    }
+}
@@ -1,22 +1,54 @@
-use std::{net::SocketAddr, error::Error, sync::Arc, env, fmt::Display};
+use std::{error::Error, env, fmt::Display, time::Duration, collections::{HashSet, HashMap}, path::PathBuf};
 use serde::{Deserialize, Serialize};
 
 use askama::Template;
 use axum::{
     routing::{get, post},
-    Router, extract::{State, Query}, Form, Json, response::IntoResponseParts,
+    Router, extract::{State, FromRef, FromRequest, Path, Query, Multipart, DefaultBodyLimit, multipart::MultipartError}, Json,
+    async_trait, body::Body,
 };
-use axum::http::{Uri, header, StatusCode};
+use axum::http::{Uri, header, StatusCode, Request};
 use axum::response::{Response, IntoResponse};
 
-use log::{info, error};
-use tower_http::services::ServeDir;
+use log::info;
 use rust_embed::RustEmbed;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
 mod knowledge;
-use knowledge::Knowledge;
+use knowledge::{Knowledge, KnowledgeConfig, WordData, TokenData, LemmaMap, TokenOverride};
+
+mod tokenizer;
+use tokenizer::{TokenizerKind, JumanppConfig, PartOfSpeech};
+
+mod user_dictionary;
+
+mod normalize;
+use normalize::NormalizationMode;
+
+mod language;
+use language::Language;
+
+mod segmenter;
+use segmenter::{SegmenterConfig, NewlineMode, EllipsisMode};
+
+mod html;
+mod epub;
+
+mod subtitles;
+use subtitles::SubtitleFormat;
+
+mod reading_hint;
+mod aozora;
+mod tatoeba;
+
+mod known_words;
+use known_words::KnownWordsFormat;
+
+mod anki;
+use anki::NoteField;
+
+mod kindle;
 
 pub static STATIC_ASSETS_PATH: &str = concat!("/assets_", env!("CARGO_PKG_VERSION"));
 
@@ -32,6 +64,8 @@ struct ErrorTemplate {
 #[derive(Debug)]
 pub enum ControllerError {
     KnowledgeError(knowledge::KnowledgeError),
+    // A file upload that's missing something or couldn't be read.
+    InvalidUpload(String),
     NotFound
 }
 
@@ -41,10 +75,17 @@ impl From<knowledge::KnowledgeError> for ControllerError {
     }
 }
 
+impl From<MultipartError> for ControllerError {
+    fn from(value: MultipartError) -> Self {
+        Self::InvalidUpload(value.to_string())
+    }
+}
+
 impl Display for ControllerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::KnowledgeError(e) => write!(f, "Error accessing knowledge: {}", e),
+            Self::InvalidUpload(reason) => write!(f, "Invalid upload: {}", reason),
             Self::NotFound => write!(f, "Not Found")
         }
     }
@@ -54,34 +95,121 @@ impl std::error::Error for ControllerError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
             Self::KnowledgeError(e) => Some(e),
-            Self::NotFound => None
+            Self::InvalidUpload(_) | Self::NotFound => None
+        }
+    }
+}
+
+impl ControllerError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            // Nothing is wrong with the request, we just can't tokenize anything right now.
+            Self::KnowledgeError(knowledge::KnowledgeError::TokenizerNotFound(_)) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::KnowledgeError(knowledge::KnowledgeError::TokenizerTimedOut(_)) => StatusCode::GATEWAY_TIMEOUT,
+            Self::KnowledgeError(knowledge::KnowledgeError::SentenceNotFound(_)) => StatusCode::NOT_FOUND,
+            Self::KnowledgeError(knowledge::KnowledgeError::InvalidTokens(_)) => StatusCode::BAD_REQUEST,
+            Self::KnowledgeError(knowledge::KnowledgeError::UnknownLanguage(_)) => StatusCode::BAD_REQUEST,
+            Self::KnowledgeError(knowledge::KnowledgeError::InvalidImport(_)) => StatusCode::BAD_REQUEST,
+            Self::KnowledgeError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidUpload(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND
         }
     }
 }
 
 impl IntoResponse for ControllerError {
     fn into_response(self) -> Response {
-        match &self {
-            Self::KnowledgeError(e) => {
-                (StatusCode::INTERNAL_SERVER_ERROR,
-                ErrorTemplate {
-                    status: StatusCode::INTERNAL_SERVER_ERROR,
-                    text: format!("{}", self).to_string()
-                }).into_response()
-            },
-            Self::NotFound => {
-                (StatusCode::NOT_FOUND,
-                ErrorTemplate {
-                    status: StatusCode::NOT_FOUND,
-                    text: format!("{}", self).to_string()
-                }).into_response()
-            }
-        }
+        let status = self.status_code();
+        (status,
+        ErrorTemplate {
+            status,
+            text: format!("{}", self)
+        }).into_response()
     }
 }
 
 pub type ControllerResult<T> = Result<T, ControllerError>;
 
+// Errors for the routes that are called from javascript, these get sent back as json rather than a page.
+#[derive(Debug)]
+pub struct ApiError(ControllerError);
+
+impl<E: Into<ControllerError>> From<E> for ApiError {
+    fn from(value: E) -> Self {
+        Self(value.into())
+    }
+}
+
+#[derive(Serialize)]
+struct ApiErrorResponse {
+    success: bool,
+    error: String
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        log::error!("{}", self.0);
+        (self.0.status_code(),
+        Json(ApiErrorResponse {
+            success: false,
+            error: format!("{}", self.0)
+        })).into_response()
+    }
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+// Everything the routes have access to.
+#[derive(Clone)]
+struct AppState {
+    knowledge: Knowledge,
+    furigana: FuriganaMode,
+    // The language to add and review when one isn't picked.
+    language: Language
+}
+
+impl FromRef<AppState> for Knowledge {
+    fn from_ref(state: &AppState) -> Self {
+        state.knowledge.clone()
+    }
+}
+
+impl FromRef<AppState> for FuriganaMode {
+    fn from_ref(state: &AppState) -> Self {
+        state.furigana
+    }
+}
+
+impl FromRef<AppState> for Language {
+    fn from_ref(state: &AppState) -> Self {
+        state.language
+    }
+}
+
+// Pick a language by its code, e.g. "es", falling back to the default language.
+fn pick_language(code: Option<&str>, default: Language) -> Result<Language, ControllerError> {
+    match code {
+        Some(code) if !code.is_empty() => Language::from_code(code)
+            .ok_or_else(|| knowledge::KnowledgeError::UnknownLanguage(code.to_string()).into()),
+        _ => Ok(default)
+    }
+}
+
+// A language to pick from on a page.
+struct LanguageOption {
+    code: &'static str,
+    name: &'static str,
+    selected: bool
+}
+
+fn language_options(selected: Language) -> Vec<LanguageOption> {
+    Language::ALL.iter().map(|language| LanguageOption {
+        code: language.code(),
+        name: language.name(),
+        selected: *language == selected
+    }).collect()
+}
+
 // Embed our assets
 #[derive(RustEmbed)]
 #[folder = "assets"]
@@ -108,60 +236,403 @@ async fn asset_handler(uri: Uri) -> ControllerResult<Response> {
 #[derive(Template)]
 #[template(path = "add.html")]
 struct AddTemplate {
+    languages: Vec<LanguageOption>
 }
 
-async fn add_get(State(knowledge): State<Knowledge>) -> ControllerResult<AddTemplate> {
-    Ok(AddTemplate { })
+async fn add_get(State(_knowledge): State<Knowledge>, State(language): State<Language>) -> ControllerResult<AddTemplate> {
+    Ok(AddTemplate {
+        languages: language_options(language)
+    })
 }
 
 #[derive(Deserialize)]
 struct AddTextQuery {
     text: String,
-    source: String
+    source: String,
+    // The language code of the text, the default language if it's not given.
+    language: Option<String>
 }
 
 #[derive(Serialize)]
 struct AddTextResponse {
     success: bool,
-    sentences_added: i64
+    sentences_added: i64,
+    // Sentences that were left out, with why.
+    sentences_rejected: Vec<String>
+}
+
+// Text to add, either typed in and sent as JSON or a saved web page uploaded with a form.
+enum AddTextRequest {
+    Text(AddTextQuery),
+    Upload(Multipart)
 }
 
-async fn add_post(State(mut knowledge): State<Knowledge>,
-                  Json(AddTextQuery{ text, source }): Json<AddTextQuery>) -> ControllerResult<Json<AddTextResponse>>
+#[async_trait]
+impl<S: Send + Sync> FromRequest<S, Body> for AddTextRequest {
+    type Rejection = Response;
+
+    async fn from_request(request: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let is_upload = request.headers().get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("multipart/form-data"));
+
+        match is_upload {
+            true => Multipart::from_request(request, state).await
+                .map(Self::Upload)
+                .map_err(IntoResponse::into_response),
+            false => Json::<AddTextQuery>::from_request(request, state).await
+                .map(|Json(query)| Self::Text(query))
+                .map_err(IntoResponse::into_response)
+        }
+    }
+}
+
+async fn add_post(State(mut knowledge): State<Knowledge>, State(default_language): State<Language>,
+                  request: AddTextRequest) -> ApiResult<Json<AddTextResponse>>
 {
-    let sentences_added = knowledge.add_text(text.as_str(), source.as_str()).await?;
+    let report = match request {
+        AddTextRequest::Text(AddTextQuery{ text, source, language }) => {
+            let language = pick_language(language.as_deref(), default_language)?;
+            knowledge.add_text(text.as_str(), source.as_str(), language).await?
+        },
+        AddTextRequest::Upload(multipart) => add_html_upload(&mut knowledge, multipart, default_language).await?
+    };
 
     Ok(Json(AddTextResponse {
         success: true,
-        sentences_added
+        sentences_added: report.sentences_added,
+        sentences_rejected: report.sentences_rejected.iter().map(ToString::to_string).collect()
     }))
 }
 
+// When to show furigana above the words in a sentence being reviewed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FuriganaMode {
+    Always,
+    // Only on words that haven't been learnt yet.
+    NewWords,
+    // Hidden until asked for.
+    AfterReveal,
+    Never
+}
+
+// How a word in the sentence being reviewed should be highlighted.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WordStatus {
+    Due,
+    New,
+    Known
+}
+
+impl WordStatus {
+    fn css_class(&self) -> &'static str {
+        match self {
+            Self::Due => "due_word",
+            Self::New => "new_word",
+            Self::Known => "known_word"
+        }
+    }
+}
+
+// A bit of the sentence being reviewed, with the reading to show above it if there is one.
+struct SentenceSegment {
+    text: String,
+    reading: Option<String>,
+    status: Option<WordStatus>
+}
+
+// Split a sentence up into segments for showing with furigana and highlighting.
+// Any text that isn't covered by a token (e.g. spaces) is kept as a segment of its own.
+fn sentence_segments(sentence: &str, tokens: &[TokenData],
+                     show_reading: impl Fn(&TokenData) -> bool,
+                     word_status: impl Fn(&TokenData) -> Option<WordStatus>) -> Vec<SentenceSegment> {
+    let chars: Vec<char> = sentence.chars().collect();
+    let mut segments = Vec::new();
+    let mut cursor = 0;
+
+    for token in tokens {
+        if token.offset < cursor || token.offset >= chars.len() {
+            continue;
+        }
+
+        if token.offset > cursor {
+            segments.push(SentenceSegment { text: chars[cursor..token.offset].iter().collect(), reading: None, status: None });
+        }
+
+        let end = token.end_offset.clamp(token.offset, chars.len());
+        let text: String = chars[token.offset..end].iter().collect();
+        let needs_reading = text.chars().any(is_kanji) && token.reading != tokenizer::to_hiragana(&text);
+        segments.push(SentenceSegment {
+            reading: (needs_reading && show_reading(token)).then(|| token.reading.clone()),
+            status: word_status(token),
+            text
+        });
+        cursor = end;
+    }
+
+    if cursor < chars.len() {
+        segments.push(SentenceSegment { text: chars[cursor..].iter().collect(), reading: None, status: None });
+    }
+
+    segments
+}
+
+fn is_kanji(c: char) -> bool {
+    matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' | '々')
+}
+
 #[derive(Template)]
 #[template(path = "review.html")]
 struct ReviewTemplate {
+    languages: Vec<LanguageOption>,
     sentence_id: i64,
-    sentence: String,
+    sentence_segments: Vec<SentenceSegment>,
+    hide_furigana: bool,
     sentence_source: String,
+    // When the sentence is said, for sentences from subtitles.
+    sentence_time: Option<String>,
+    // Shown once the sentence has been graded.
+    sentence_translation: Option<String>,
     reviews_today_count: i64,
     words_being_reviewed: Vec<String>,
     words_that_are_new: Vec<String>
 }
 
-async fn review_get(State(knowledge): State<Knowledge>) -> ControllerResult<ReviewTemplate> {
-    let review_info = knowledge.get_review_info().await?;
-    let sentence_data = knowledge.get_next_sentence_i_plus_one().await?;
+#[derive(Deserialize)]
+struct ReviewPageQuery {
+    language: Option<String>
+}
+
+async fn review_get(State(knowledge): State<Knowledge>, State(furigana): State<FuriganaMode>, State(default_language): State<Language>,
+                    Query(ReviewPageQuery{ language }): Query<ReviewPageQuery>) -> ControllerResult<ReviewTemplate> {
+    let language = pick_language(language.as_deref(), default_language)?;
+    let review_info = knowledge.get_review_info(language).await?;
+    let sentence_data = knowledge.get_next_sentence_i_plus_one(language).await?;
+
+    let due_word_ids: HashSet<i64> = sentence_data.words_being_reviewed.iter().map(|word| word.id).collect();
+    let new_word_ids: HashSet<i64> = sentence_data.words_that_are_new.iter().map(|word| word.id).collect();
+    let word_status = |token: &TokenData| match token.word_id {
+        _ if token.excluded => None,
+        Some(id) if due_word_ids.contains(&id) => Some(WordStatus::Due),
+        Some(id) if new_word_ids.contains(&id) => Some(WordStatus::New),
+        Some(_) => Some(WordStatus::Known),
+        None => None
+    };
+
+    let sentence_segments = sentence_segments(&sentence_data.sentence_text, &sentence_data.tokens,
+        |token| match furigana {
+            FuriganaMode::Always | FuriganaMode::AfterReveal => true,
+            FuriganaMode::NewWords => word_status(token) == Some(WordStatus::New),
+            FuriganaMode::Never => false
+        },
+        word_status);
 
     Ok(ReviewTemplate {
+        languages: language_options(language),
         sentence_id: sentence_data.sentence_id,
-        sentence: sentence_data.sentence_text,
+        sentence_segments,
+        hide_furigana: furigana == FuriganaMode::AfterReveal,
         sentence_source: sentence_data.sentence_source,
+        sentence_time: sentence_data.sentence_timing.map(|timing| format_time(timing.start_ms)),
+        sentence_translation: sentence_data.sentence_translation,
         reviews_today_count: review_info.reviews_remaining,
-        words_being_reviewed: sentence_data.words_being_reviewed.iter().map(|(_, text)| text.clone()).collect(),
-        words_that_are_new: sentence_data.words_that_are_new.iter().map(|(_, text)| text.clone()).collect()
+        words_being_reviewed: sentence_data.words_being_reviewed.iter().map(display_word).collect(),
+        words_that_are_new: sentence_data.words_that_are_new.iter().map(display_word).collect()
+    })
+}
+
+// A time in a video like 12:34, or 1:02:03 if it's over an hour in.
+fn format_time(ms: i64) -> String {
+    let seconds = ms / 1000;
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, (seconds / 60) % 60, seconds % 60)
+    }
+}
+
+// Show the reading next to the word when it isn't obvious, so that homographs can be told apart.
+// If the word is one spelling of a group of variants, say which group so it's clear which spelling appeared.
+fn display_word(word: &WordData) -> String {
+    let mut display = if word.reading.is_empty() || tokenizer::to_hiragana(&word.text) == word.reading {
+        word.text.clone()
+    } else {
+        format!("{} ({})", word.text, word.reading)
+    };
+
+    if let Some(representative) = word.lemma_group.as_deref().and_then(|group| group.split('/').next()) {
+        if representative != word.text {
+            display.push_str(&format!(" [spelling of {}]", representative));
+        }
+    }
+
+    display
+}
+
+// A row of the token correction page, the current word is shown as a placeholder for anything the user hasn't set.
+struct TokenRow {
+    surface: String,
+    dictionary_form: String,
+    reading: String,
+    pos: String,
+    current_dictionary_form: String,
+    current_reading: String,
+    current_pos: String
+}
+
+#[derive(Template)]
+#[template(path = "tokens.html")]
+struct TokensTemplate {
+    sentence_id: i64,
+    sentence_text: String,
+    tokens: Vec<TokenRow>,
+    overridden: bool,
+    pos_names: Vec<&'static str>
+}
+
+async fn tokens_get(State(knowledge): State<Knowledge>, Path(sentence_id): Path<i64>) -> ControllerResult<TokensTemplate> {
+    let data = knowledge.get_sentence_tokens(sentence_id).await?;
+
+    Ok(TokensTemplate {
+        sentence_id: data.sentence_id,
+        sentence_text: data.sentence_text,
+        tokens: data.tokens.into_iter().map(|token| TokenRow {
+            surface: token.surface,
+            dictionary_form: token.dictionary_form.unwrap_or_default(),
+            reading: token.reading.unwrap_or_default(),
+            pos: token.pos.unwrap_or_default(),
+            current_dictionary_form: token.word_text.unwrap_or_default(),
+            current_reading: token.word_reading.unwrap_or_default(),
+            current_pos: token.word_pos.unwrap_or_default()
+        }).collect(),
+        overridden: data.overridden,
+        pos_names: PartOfSpeech::ALL.iter().map(PartOfSpeech::as_str).collect()
     })
 }
 
+#[derive(Deserialize)]
+struct TokenQuery {
+    surface: String,
+    dictionary_form: Option<String>,
+    reading: Option<String>,
+    pos: Option<String>
+}
+
+#[derive(Deserialize)]
+struct TokensQuery {
+    tokens: Vec<TokenQuery>
+}
+
+#[derive(Serialize)]
+struct TokensResponse {
+    success: bool
+}
+
+async fn tokens_post(State(mut knowledge): State<Knowledge>, Path(sentence_id): Path<i64>,
+                     Json(TokensQuery{ tokens }): Json<TokensQuery>) -> ApiResult<Json<TokensResponse>> {
+    let non_empty = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+
+    let mut overrides = Vec::new();
+    for token in tokens {
+        let pos = match non_empty(token.pos) {
+            Some(name) => Some(PartOfSpeech::from_name(&name)
+                .ok_or_else(|| knowledge::KnowledgeError::InvalidTokens(format!("Unknown part of speech '{}'", name)))?),
+            None => None
+        };
+
+        overrides.push(TokenOverride {
+            surface: token.surface,
+            dictionary_form: non_empty(token.dictionary_form),
+            reading: non_empty(token.reading),
+            pos
+        });
+    }
+
+    knowledge.set_sentence_tokens(sentence_id, overrides).await?;
+
+    Ok(Json(TokensResponse {
+        success: true
+    }))
+}
+
+async fn tokens_delete(State(mut knowledge): State<Knowledge>, Path(sentence_id): Path<i64>) -> ApiResult<Json<TokensResponse>> {
+    knowledge.clear_sentence_tokens(sentence_id).await?;
+
+    Ok(Json(TokensResponse {
+        success: true
+    }))
+}
+
+// Add an uploaded web page, with its title as the source if one isn't given.
+async fn add_html_upload(knowledge: &mut Knowledge, mut multipart: Multipart, default_language: Language) -> ApiResult<knowledge::AddTextReport> {
+    let mut file = None;
+    let mut file_name = None;
+    let mut source = None;
+    let mut language = None;
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("file") => {
+                file_name = field.file_name()
+                    .map(|name| name.rsplit_once('.').map_or(name, |(stem, _)| stem).to_string());
+                file = Some(field.bytes().await?);
+            },
+            Some("source") => source = Some(field.text().await?).filter(|source| !source.trim().is_empty()),
+            Some("language") => language = Some(field.text().await?),
+            _ => {}
+        }
+    }
+
+    let file = file.ok_or_else(|| ControllerError::InvalidUpload("No file was uploaded".to_string()))?;
+    let language = pick_language(language.as_deref(), default_language)?;
+    Ok(html::import_html(knowledge, &file, source.as_deref().map(str::trim), file_name.as_deref(), language).await?)
+}
+
+// Add every chapter of an uploaded EPUB book.
+async fn add_epub_post(State(mut knowledge): State<Knowledge>, State(default_language): State<Language>,
+                       mut multipart: Multipart) -> ApiResult<Json<AddTextResponse>>
+{
+    let mut file = None;
+    let mut language = None;
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("file") => file = Some(field.bytes().await?),
+            Some("language") => language = Some(field.text().await?),
+            _ => {}
+        }
+    }
+
+    let file = file.ok_or_else(|| ControllerError::InvalidUpload("No file was uploaded".to_string()))?;
+    let language = pick_language(language.as_deref(), default_language)?;
+    let report = epub::import_epub(&mut knowledge, std::io::Cursor::new(file), language).await?;
+
+    Ok(Json(AddTextResponse {
+        success: true,
+        sentences_added: report.sentences_added,
+        sentences_rejected: report.sentences_rejected.iter().map(ToString::to_string).collect()
+    }))
+}
+
+#[derive(Deserialize)]
+struct ContextQuery {
+    // How many sentences to show either side.
+    count: Option<i64>
+}
+
+#[derive(Serialize)]
+struct ContextResponse {
+    before: Vec<String>,
+    after: Vec<String>
+}
+
+async fn context_get(State(knowledge): State<Knowledge>, Path(sentence_id): Path<i64>,
+                     Query(ContextQuery{ count }): Query<ContextQuery>) -> ApiResult<Json<ContextResponse>> {
+    let context = knowledge.get_sentence_context(sentence_id, count.unwrap_or(2).max(0)).await?;
+
+    Ok(Json(ContextResponse {
+        before: context.before,
+        after: context.after
+    }))
+}
+
 #[derive(Deserialize)]
 struct ReviewQuery {
     review_sentence_id: i64,
@@ -174,7 +645,7 @@ struct ReviewResponse {
 }
 
 async fn review_post(State(knowledge): State<Knowledge>,
-                     Json(ReviewQuery{ review_sentence_id, response_quality }): Json<ReviewQuery>) -> ControllerResult<Json<ReviewResponse>> {
+                     Json(ReviewQuery{ review_sentence_id, response_quality }): Json<ReviewQuery>) -> ApiResult<Json<ReviewResponse>> {
     info!("Reviewing with {} quality", response_quality);
     knowledge.review_sentence(review_sentence_id, response_quality).await?;
 
@@ -186,9 +657,249 @@ async fn review_post(State(knowledge): State<Knowledge>,
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     // Whether or not to re-tokenize sentences.
     #[arg(short, long)]
-    retokenize: bool
+    retokenize: bool,
+
+    // Only re-tokenize sentences that were tokenized by a different tokenizer or version.
+    #[arg(long)]
+    retokenize_stale: bool,
+
+    // Print what re-tokenizing would change and exit without changing anything.
+    // The database is opened read only, so this can't be combined with anything that writes to it.
+    #[arg(long, conflicts_with_all = ["retokenize", "retokenize_stale", "variants", "user_dictionary", "merge_duplicates"])]
+    retokenize_dry_run: bool,
+
+    // A file of dictionary forms that have changed between tokenizers, an old and a new form on each line separated by a tab.
+    // Progress on the old words is carried over to the new ones when re-tokenizing.
+    #[arg(long)]
+    lemma_map: Option<PathBuf>,
+
+    // A file of variant spellings in the --language to group together, a variant and its representative form on each line separated by a tab.
+    #[arg(long)]
+    variants: Option<PathBuf>,
+
+    // A file of forced segmentations to add to the user dictionary, see UserDictionaryEntry::parse for the format.
+    #[arg(long)]
+    user_dictionary: Option<PathBuf>,
+
+    // How many sentences to re-tokenize per transaction with --retokenize-stale.
+    #[arg(long, default_value_t = 500)]
+    retokenize_chunk_size: i64,
+
+    // Which tokenizer to use to split sentences into words.
+    #[arg(short, long, value_enum, default_value_t = TokenizerKind::Jumanpp)]
+    tokenizer: TokenizerKind,
+
+    // How many jumanpp processes to keep running for tokenizing.
+    #[arg(long, default_value_t = 1)]
+    jumanpp_workers: usize,
+
+    // How many seconds to wait on jumanpp for a single sentence before giving up.
+    #[arg(long, default_value_t = 30)]
+    jumanpp_timeout: u64,
+
+    // Words with these parts of speech won't be reviewed. Pass with no values to review everything.
+    #[arg(long, value_enum, value_delimiter = ',', num_args = 0.., default_values_t = PartOfSpeech::DEFAULT_EXCLUDED)]
+    exclude_pos: Vec<PartOfSpeech>,
+
+    // When to show furigana on the review page.
+    #[arg(long, value_enum, default_value_t = FuriganaMode::Always)]
+    furigana: FuriganaMode,
+
+    // How sentences and words are normalized before they're stored.
+    #[arg(long, value_enum, default_value_t = NormalizationMode::Japanese)]
+    normalization: NormalizationMode,
+
+    // Normalize everything already in the database and merge the duplicates that turn up.
+    #[arg(long)]
+    merge_duplicates: bool,
+
+    // The language to add and review when one isn't picked.
+    #[arg(long, value_enum, default_value_t = Language::Japanese)]
+    language: Language,
+
+    // A frequency list to use for a language, as language=path, e.g. spanish=spanish_frequency.txt.
+    // The file has one word per line, most frequent first.
+    #[arg(long, value_parser = parse_frequency_list)]
+    frequency_list: Vec<(Language, PathBuf)>,
+
+    // The characters that end a sentence, e.g. "。！？". Defaults to the language's usual ones.
+    #[arg(long)]
+    sentence_terminators: Option<String>,
+
+    // Pairs of brackets that a sentence carries on inside of, e.g. 「」,『』,（）. Defaults to the language's usual ones.
+    #[arg(long, value_parser = parse_bracket_pair, value_delimiter = ',')]
+    brackets: Vec<(char, char)>,
+
+    // Whether every newline ends a sentence or only blank lines do.
+    #[arg(long, value_enum, default_value_t = NewlineMode::Hard)]
+    newlines: NewlineMode,
+
+    // Whether an ellipsis like …… ends a sentence.
+    #[arg(long, value_enum, default_value_t = EllipsisMode::Continue)]
+    ellipsis: EllipsisMode,
+
+    // Sentences with fewer characters than this aren't added.
+    #[arg(long, default_value_t = 1)]
+    min_sentence_length: usize,
+
+    // Sentences with more characters than this aren't added.
+    #[arg(long)]
+    max_sentence_length: Option<usize>
+}
+
+fn parse_bracket_pair(value: &str) -> Result<(char, char), String> {
+    let mut chars = value.trim().chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(open), Some(close), None) => Ok((open, close)),
+        _ => Err(format!("Expected an opening and a closing bracket, got '{}'", value))
+    }
+}
+
+// Things to do instead of starting the server.
+#[derive(Subcommand, Debug)]
+enum Command {
+    // Add sentences from a file.
+    #[command(subcommand)]
+    Import(Import)
+}
+
+#[derive(Subcommand, Debug)]
+enum Import {
+    // Add every chapter of an EPUB book in the --language, with the book and chapter as the source.
+    Epub {
+        path: PathBuf
+    },
+    // Add the main content of saved web pages in the --language.
+    Html {
+        paths: Vec<PathBuf>,
+        // The source to give the sentences, the page's title if it's not given.
+        #[arg(long)]
+        source: Option<String>
+    },
+    // Add the lines of SRT, ASS or WebVTT subtitles in the --language, with when each one is said.
+    Subtitles {
+        paths: Vec<PathBuf>,
+        // The source to give the sentences, the file's name (e.g. the episode) if it's not given.
+        #[arg(long)]
+        source: Option<String>
+    },
+    // Add an Aozora Bunko text, in Shift_JIS or UTF-8, using its furigana for the readings of the words.
+    Aozora {
+        path: PathBuf,
+        // The source to give the sentences, the title and author if it's not given.
+        #[arg(long)]
+        source: Option<String>
+    },
+    // Add the sentences in the --language from a Tatoeba dump that have a translation, along with the translation.
+    Tatoeba {
+        // The sentences.csv from the dump.
+        sentences: PathBuf,
+        // The links.csv from the dump.
+        links: PathBuf,
+        // The ISO 639-3 code of the language to take the translations from, e.g. eng or fra.
+        #[arg(long, default_value = "eng")]
+        translation_language: String
+    },
+    // Mark the words in the --language on a list as already known: a word on each line (with an optional tab and reading),
+    // a CSV file or an Anki "Notes in Plain Text" export.
+    KnownWords {
+        path: PathBuf,
+        // The column of a CSV file or Anki export with the words in, counting from 1.
+        #[arg(long, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        column: usize,
+        // The column with the readings in, if there is one.
+        #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        reading_column: Option<usize>,
+        // How many days until the words are first reviewed.
+        #[arg(long, default_value_t = 180)]
+        interval_days: i64
+    },
+    // Add the highlights in the --language from a Kindle's My Clippings.txt, or the sentences words were looked up in from its vocab.db.
+    // Words that were looked up are learnt before other new words.
+    Kindle {
+        path: PathBuf
+    },
+    // Carry over the review schedules of the words in the --language from an Anki collection.anki2 or .apkg,
+    // so words that are already being studied there don't start over.
+    Anki {
+        path: PathBuf,
+        // The field of the notes with the words in, by name (e.g. Expression) or position counting from 1.
+        #[arg(long, default_value = "1")]
+        field: NoteField
+    }
+}
+
+async fn run_import(knowledge: &mut Knowledge, import: &Import, language: Language) -> Result<(), Box<dyn Error>> {
+    let report = match import {
+        Import::Epub { path } => epub::import_epub(knowledge, std::fs::File::open(path)?, language).await?,
+        Import::Html { paths, source } => {
+            let mut report = knowledge::AddTextReport::default();
+            for path in paths {
+                let file_name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned());
+                report.extend(html::import_html(knowledge, &std::fs::read(path)?, source.as_deref(), file_name.as_deref(), language).await?);
+            }
+            report
+        },
+        Import::Subtitles { paths, source } => {
+            let mut report = knowledge::AddTextReport::default();
+            for path in paths {
+                let contents = std::fs::read_to_string(path)?;
+                let format = SubtitleFormat::detect(Some(path), &contents);
+                let source = source.clone()
+                    .unwrap_or_else(|| path.file_stem().unwrap_or_default().to_string_lossy().into_owned());
+                report.extend(subtitles::import_subtitles(knowledge, &contents, format, &source, language).await?);
+            }
+            report
+        },
+        Import::Aozora { path, source } => aozora::import_aozora(knowledge, &std::fs::read(path)?, source.as_deref()).await?,
+        Import::Tatoeba { sentences, links, translation_language } => {
+            let sentences = std::io::BufReader::new(std::fs::File::open(sentences)?);
+            let links = std::io::BufReader::new(std::fs::File::open(links)?);
+            tatoeba::import_tatoeba(knowledge, sentences, links, language, translation_language).await?
+        },
+        Import::KnownWords { path, column, reading_column, interval_days } => {
+            let contents = std::fs::read_to_string(path)?;
+            let format = KnownWordsFormat::detect(path, &contents);
+            let words = known_words::parse(&contents, format, known_words::Columns { word: *column, reading: *reading_column });
+            let report = knowledge.mark_known_words(language, &words, *interval_days).await?;
+
+            println!("Marked {} of the {} words on the list as known, the others will be when they turn up", report.words_marked, report.entries);
+            return Ok(());
+        },
+        Import::Kindle { path } => {
+            let report = kindle::import_kindle(knowledge, path, language).await?;
+            if report.words_looked_up > 0 {
+                println!("Found {} words that were looked up, they'll be learnt first", report.words_looked_up);
+            }
+            report.sentences
+        },
+        Import::Anki { path, field } => {
+            let report = anki::import_anki(knowledge, path, field, language).await?;
+
+            println!("Carried over the schedules of {} of the {} words from Anki, the others will be when they turn up", report.words_marked, report.entries);
+            return Ok(());
+        }
+    };
+
+    for rejected in &report.sentences_rejected {
+        println!("Left out: {}", rejected);
+    }
+    println!("Added {} sentences", report.sentences_added);
+
+    Ok(())
+}
+
+fn parse_frequency_list(value: &str) -> Result<(Language, PathBuf), String> {
+    let (language, path) = value.split_once('=')
+        .ok_or_else(|| format!("Expected language=path, got '{}'", value))?;
+    let language = Language::from_str(language, true)?;
+
+    Ok((language, PathBuf::from(path)))
 }
 
 #[tokio::main]
@@ -203,11 +914,62 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
     // Create the knowledge database.
-    let mut knowledge = knowledge::Knowledge::new().await?;
+    let tokenizers = tokenizer::create_tokenizers(args.tokenizer, JumanppConfig {
+        workers: args.jumanpp_workers,
+        timeout: Duration::from_secs(args.jumanpp_timeout)
+    })?;
+    let mut knowledge = knowledge::Knowledge::new(tokenizers, KnowledgeConfig {
+        excluded_pos: args.exclude_pos.into_iter().collect(),
+        normalization: args.normalization,
+        frequency_lists: args.frequency_list.into_iter().collect::<HashMap<_, _>>(),
+        segmenter: SegmenterConfig {
+            terminators: args.sentence_terminators.map(|terminators| terminators.chars().collect()),
+            brackets: Some(args.brackets).filter(|brackets| !brackets.is_empty()),
+            newlines: args.newlines,
+            ellipsis: args.ellipsis,
+            min_length: args.min_sentence_length,
+            max_length: args.max_sentence_length
+        },
+        read_only: args.retokenize_dry_run
+    }).await?;
+
+    // Stop before anything else writes to the database, which is read only for a dry run.
+    if args.retokenize_dry_run {
+        let report = knowledge.retokenize_dry_run().await?;
+        println!("{}", report);
+        return Ok(());
+    }
+
+    // Add any variant spellings.
+    if let Some(path) = &args.variants {
+        knowledge.load_variants(args.language, path).await?;
+    }
+
+    // Add to the user dictionary.
+    if let Some(path) = &args.user_dictionary {
+        knowledge.load_user_dictionary(path).await?;
+    }
+
+    if args.merge_duplicates {
+        knowledge.merge_duplicates().await?;
+    }
 
     // Retokenize our db if specified.
+    let lemma_map = match &args.lemma_map {
+        Some(path) => LemmaMap::load(path)?,
+        None => LemmaMap::default()
+    };
     if args.retokenize {
-        knowledge.retokenize().await?
+        knowledge.retokenize(&lemma_map).await?
+    }
+    else if args.retokenize_stale {
+        knowledge.retokenize_stale(args.retokenize_chunk_size, &lemma_map).await?
+    }
+
+    // Run a one off command instead of the server.
+    if let Some(Command::Import(import)) = &args.command {
+        run_import(&mut knowledge, import, args.language).await?;
+        return Ok(());
     }
 
     // Create the routes.
@@ -215,9 +977,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .route("/", get(review_get))
         .route("/review", post(review_post))
         .route("/add", get(add_get))
-        .route("/add", post(add_post))
+        // Saved web pages can have their images in them.
+        .route("/add", post(add_post).layer(DefaultBodyLimit::max(64 * 1024 * 1024)))
+        // Books are a lot bigger than the default limit.
+        .route("/add/epub", post(add_epub_post).layer(DefaultBodyLimit::max(256 * 1024 * 1024)))
+        .route("/sentence/:sentence_id/tokens", get(tokens_get).post(tokens_post).delete(tokens_delete))
+        .route("/sentence/:sentence_id/context", get(context_get))
         .nest_service("/assets", asset_routes())
-        .with_state(knowledge);
+        .with_state(AppState {
+            knowledge,
+            furigana: args.furigana,
+            language: args.language
+        });
 
     // Start the server.
     axum::Server::bind(&"0.0.0.0:49494".parse().unwrap())
@@ -0,0 +1,107 @@
+use clap::ValueEnum;
+use unicode_normalization::UnicodeNormalization;
+
+// How text is folded before it's stored, so that the same sentence or word typed slightly
+// differently (full-width letters, a different wave dash, a stray zero-width space) isn't stored twice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum NormalizationMode {
+    // NFKC, except for the full-width punctuation that Japanese text normally uses.
+    Japanese,
+    // Plain NFKC, full-width punctuation becomes half-width too.
+    Nfkc,
+    // Store text exactly as it's given.
+    None
+}
+
+// Characters that don't show up but still make two strings different.
+const INVISIBLE: [char; 5] = ['\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', '\u{FEFF}'];
+
+// Full-width characters that NFKC would turn into ASCII, but that are the normal way of writing them in Japanese.
+// Sentences are split on some of these too. NFKC would also turn the spacing (han)dakuten into a space and a combining mark.
+const JAPANESE_KEPT: [char; 12] = ['！', '？', '（', '）', '：', '；', '，', '…', '‥', '\u{3000}', '゛', '゜'];
+
+impl NormalizationMode {
+    pub fn normalize(&self, text: &str) -> String {
+        let text: String = text.chars().filter(|c| !INVISIBLE.contains(c)).collect();
+
+        match self {
+            Self::Japanese => {
+                let mut normalized = String::with_capacity(text.len());
+                let mut rest = String::new();
+                for c in text.chars() {
+                    // All the different wave dashes become the Japanese one rather than NFKC's ASCII tilde.
+                    let kept = match c {
+                        '～' | '〜' => Some('〜'),
+                        c if JAPANESE_KEPT.contains(&c) => Some(c),
+                        _ => None
+                    };
+
+                    match kept {
+                        Some(kept) => {
+                            normalized.extend(rest.drain(..).nfkc());
+                            normalized.push(kept);
+                        },
+                        None => rest.push(c)
+                    }
+                }
+                normalized.extend(rest.nfkc());
+                normalized
+            },
+            Self::Nfkc => text.nfkc().collect(),
+            Self::None => text
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn japanese_folds_full_and_half_width_forms() {
+        assert_eq!(NormalizationMode::Japanese.normalize("ＡＢＣ１２３"), "ABC123");
+        assert_eq!(NormalizationMode::Japanese.normalize("ﾈｺﾁｬﾝ"), "ネコチャン");
+        assert_eq!(NormalizationMode::Japanese.normalize("ｶﾞｯｺｳ"), "ガッコウ");
+    }
+
+    #[test]
+    fn japanese_keeps_full_width_punctuation() {
+        assert_eq!(NormalizationMode::Japanese.normalize("本当！？（笑）"), "本当！？（笑）");
+        assert_eq!(NormalizationMode::Japanese.normalize("えっと…　はい‥"), "えっと…　はい‥");
+    }
+
+    #[test]
+    fn japanese_unifies_wave_dashes() {
+        assert_eq!(NormalizationMode::Japanese.normalize("すご～い"), "すご〜い");
+        assert_eq!(NormalizationMode::Japanese.normalize("すご〜い"), "すご〜い");
+    }
+
+    #[test]
+    fn japanese_keeps_spacing_dakuten() {
+        assert_eq!(NormalizationMode::Japanese.normalize("あ゛"), "あ゛");
+    }
+
+    #[test]
+    fn nfkc_makes_punctuation_half_width() {
+        assert_eq!(NormalizationMode::Nfkc.normalize("本当！？"), "本当!?");
+        assert_eq!(NormalizationMode::Nfkc.normalize("ﾈｺ…"), "ネコ...");
+    }
+
+    #[test]
+    fn invisible_characters_are_dropped() {
+        for mode in [NormalizationMode::Japanese, NormalizationMode::Nfkc, NormalizationMode::None] {
+            assert_eq!(mode.normalize("\u{FEFF}猫\u{200B}が好き\u{2060}"), "猫が好き");
+        }
+    }
+
+    #[test]
+    fn none_leaves_the_text_alone() {
+        assert_eq!(NormalizationMode::None.normalize("ＡＢＣ～ﾈｺ"), "ＡＢＣ～ﾈｺ");
+    }
+
+    #[test]
+    fn normalizing_twice_changes_nothing() {
+        let once = NormalizationMode::Japanese.normalize("ＡＢＣ！ﾈｺ～…");
+        assert_eq!(NormalizationMode::Japanese.normalize(&once), once);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::tokenizer::{Morpheme, is_kana, to_hiragana};
+
+// Furigana that came with a text, e.g. 漢字《かんじ》 in Aozora Bunko, before we know where in a sentence it is.
+#[derive(Clone, Debug)]
+pub struct Ruby {
+    pub base: String,
+    pub reading: String
+}
+
+// How a span of a sentence is read, used over the tokenizer's guess.
+#[derive(Clone, Debug)]
+pub struct ReadingHint {
+    pub start: usize,
+    pub end: usize,
+    pub reading: String
+}
+
+// Find where each ruby is in a sentence, in order. Any that aren't in it are left for the next sentence.
+pub fn locate(sentence: &str, ruby: &mut Vec<Ruby>) -> Vec<ReadingHint> {
+    let chars: Vec<char> = sentence.chars().collect();
+    let mut hints = Vec::new();
+    let mut cursor = 0;
+
+    while let Some(next) = ruby.first() {
+        let base: Vec<char> = next.base.chars().collect();
+        if base.is_empty() {
+            ruby.remove(0);
+            continue;
+        }
+
+        let found = (cursor..chars.len().saturating_sub(base.len() - 1))
+            .find(|start| chars[*start..*start + base.len()] == base[..]);
+        let Some(start) = found else {
+            break;
+        };
+
+        hints.push(ReadingHint { start, end: start + base.len(), reading: next.reading.clone() });
+        cursor = start + base.len();
+        ruby.remove(0);
+    }
+
+    hints
+}
+
+// Use the hints for the readings of the morphemes they're in.
+// The parts of a morpheme outside of a hint have to be kana (like the okurigana of 聞いた with 聞《き》) so we know how they're read,
+// otherwise the tokenizer's reading is kept.
+pub fn apply(sentence: &str, morphemes: Vec<Morpheme>, hints: &[ReadingHint]) -> Vec<Morpheme> {
+    if hints.is_empty() {
+        return morphemes;
+    }
+
+    let chars: Vec<char> = sentence.chars().collect();
+    morphemes.into_iter().map(|mut morpheme| {
+        let start = morpheme.offset;
+        let end = start + morpheme.surface.chars().count();
+        if !hints.iter().any(|hint| start <= hint.start && hint.end <= end) {
+            return morpheme;
+        }
+
+        let mut reading = String::new();
+        let mut position = start;
+        while position < end {
+            if let Some(hint) = hints.iter().find(|hint| hint.start == position && hint.end <= end) {
+                reading.push_str(&hint.reading);
+                position = hint.end;
+            }
+            else if chars.get(position).is_some_and(|c| is_kana(*c)) {
+                reading.push_str(&to_hiragana(&chars[position].to_string()));
+                position += 1;
+            }
+            else {
+                return morpheme;
+            }
+        }
+
+        // Work the dictionary form's reading out again from the new reading, keeping the representative form's spelling.
+        let mut plain = morpheme.clone();
+        plain.semantic_info = String::new();
+        plain.reading = reading.clone();
+        let lemma_reading = plain.lemma_reading();
+        let representative = morpheme.representative_form()
+            .and_then(|rep| rep.split_once('/').map(|(text, _)| text.to_string()))
+            .unwrap_or_else(|| morpheme.dictionary_form.clone());
+
+        let other_info = morpheme.semantic_info.split(' ')
+            .filter(|item| !item.is_empty() && !item.starts_with("代表表記:"))
+            .collect::<Vec<_>>();
+        morpheme.semantic_info = std::iter::once(format!("代表表記:{}/{}", representative, lemma_reading))
+            .chain(other_info.into_iter().map(str::to_string))
+            .collect::<Vec<_>>()
+            .join(" ");
+        morpheme.reading = reading;
+        // The representative form now has our reading in it, so it's no longer the tokenizer's group for the word.
+        morpheme.synthetic_representative = true;
+
+        morpheme
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ruby(base: &str, reading: &str) -> Ruby {
+        Ruby { base: base.to_string(), reading: reading.to_string() }
+    }
+
+    fn morpheme(surface: &str, reading: &str, dictionary_form: &str, semantic_info: &str, offset: usize) -> Morpheme {
+        Morpheme {
+            surface: surface.to_string(),
+            reading: reading.to_string(),
+            dictionary_form: dictionary_form.to_string(),
+            semantic_info: semantic_info.to_string(),
+            offset,
+            ..Morpheme::default()
+        }
+    }
+
+    #[test]
+    fn locates_ruby_in_order() {
+        let mut pending = vec![ruby("猫", "ねこ"), ruby("猫", "びょう"), ruby("犬", "いぬ")];
+        let hints = locate("猫と猫。", &mut pending);
+
+        let found: Vec<_> = hints.iter().map(|hint| (hint.start, hint.end, hint.reading.as_str())).collect();
+        assert_eq!(found, [(0, 1, "ねこ"), (2, 3, "びょう")]);
+        // The rest is in a later sentence.
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].base, "犬");
+    }
+
+    #[test]
+    fn locate_skips_empty_bases() {
+        let mut pending = vec![ruby("", "なし"), ruby("吾輩", "わがはい")];
+        let hints = locate("吾輩は猫", &mut pending);
+
+        assert_eq!(hints.len(), 1);
+        assert_eq!((hints[0].start, hints[0].end), (0, 2));
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn locate_handles_a_base_longer_than_the_sentence() {
+        let mut pending = vec![ruby("長い漢字", "ながいかんじ")];
+        assert!(locate("漢字", &mut pending).is_empty());
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[test]
+    fn applies_hints_with_okurigana() {
+        let hints = [ReadingHint { start: 0, end: 1, reading: "き".to_string() }];
+        let morphemes = vec![morpheme("聞いた", "ぶんいた", "聞く", "代表表記:聞く/ぶんく", 0)];
+        let applied = apply("聞いた", morphemes, &hints);
+
+        assert_eq!(applied[0].reading, "きいた");
+        assert_eq!(applied[0].semantic_value("代表表記"), Some("聞く/きく"));
+        assert_eq!(applied[0].lemma_reading(), "きく");
+        // The reading is ours, so it doesn't say which spellings go together.
+        assert!(applied[0].synthetic_representative);
+        assert_eq!(applied[0].lemma_group(), None);
+    }
+
+    #[test]
+    fn keeps_the_other_semantic_info() {
+        let hints = [ReadingHint { start: 0, end: 2, reading: "じんじん".to_string() }];
+        let morphemes = vec![morpheme("人参", "にんじん", "人参", "代表表記:人参/にんじん カテゴリ:植物", 0)];
+        let applied = apply("人参", morphemes, &hints);
+
+        assert_eq!(applied[0].semantic_info, "代表表記:人参/じんじん カテゴリ:植物");
+    }
+
+    #[test]
+    fn leaves_morphemes_without_a_whole_hint_alone() {
+        // The hint is only on part of the kanji, so there's no knowing how the rest is read.
+        let hints = [ReadingHint { start: 0, end: 1, reading: "とう".to_string() }];
+        let morphemes = vec![
+            morpheme("東京", "とうきょう", "東京", "代表表記:東京/とうきょう", 0),
+            morpheme("だ", "だ", "だ", "", 2)
+        ];
+        let applied = apply("東京だ", morphemes.clone(), &hints);
+
+        assert_eq!(applied, morphemes);
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt::Display;
+
+use clap::ValueEnum;
+
+use crate::language::Language;
+
+// What a newline in the text means.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum NewlineMode {
+    // Every line is its own sentence, e.g. subtitles or lists of example sentences.
+    Hard,
+    // Only a blank line ends a sentence, for prose that's been wrapped at a fixed width.
+    Paragraph
+}
+
+// What an ellipsis (…… or ...) means.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EllipsisMode {
+    // The sentence carries on after it, e.g. "それは……わからない。"
+    Continue,
+    // It ends the sentence like a full stop would.
+    Terminate
+}
+
+// How text is split up into sentences. The terminators and brackets default to the language's own.
+#[derive(Clone, Debug)]
+pub struct SegmenterConfig {
+    pub terminators: Option<Vec<char>>,
+    // Opening and closing brackets (or quotes) that a sentence carries on inside of.
+    pub brackets: Option<Vec<(char, char)>>,
+    pub newlines: NewlineMode,
+    pub ellipsis: EllipsisMode,
+    // Sentences outside of these lengths (in characters) aren't added.
+    pub min_length: usize,
+    pub max_length: Option<usize>
+}
+
+impl Default for SegmenterConfig {
+    fn default() -> Self {
+        Self {
+            terminators: None,
+            brackets: None,
+            newlines: NewlineMode::Hard,
+            ellipsis: EllipsisMode::Continue,
+            min_length: 1,
+            max_length: None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RejectReason {
+    TooShort,
+    TooLong
+}
+
+// A bit of text that looked like a sentence but was filtered out.
+#[derive(Debug)]
+pub struct RejectedSentence {
+    pub text: String,
+    pub reason: RejectReason
+}
+
+impl Display for RejectedSentence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.reason {
+            RejectReason::TooShort => write!(f, "{} (too short)", self.text),
+            RejectReason::TooLong => write!(f, "{} (too long)", self.text)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Segmentation {
+    pub sentences: Vec<String>,
+    pub rejected: Vec<RejectedSentence>
+}
+
+pub struct Segmenter {
+    terminators: Vec<char>,
+    brackets: Vec<(char, char)>,
+    newlines: NewlineMode,
+    ellipsis: EllipsisMode,
+    min_length: usize,
+    max_length: Option<usize>,
+    // Lines of a wrapped paragraph are joined with a space in languages that put spaces between words.
+    join_lines_with_space: bool
+}
+
+impl Segmenter {
+    pub fn new(language: Language, config: &SegmenterConfig) -> Self {
+        let (terminators, brackets): (&[char], &[(char, char)]) = match language {
+            Language::Japanese => (&['。', '！', '？', '!', '?'], &[('「', '」'), ('『', '』'), ('（', '）'), ('(', ')')]),
+            Language::Spanish | Language::English => (&['.', '!', '?', '！', '？'], &[('(', ')'), ('«', '»'), ('“', '”')]),
+            Language::Korean => (&['.', '!', '?', '。', '！', '？'], &[('(', ')'), ('“', '”'), ('「', '」'), ('『', '』')])
+        };
+
+        Self {
+            terminators: config.terminators.clone().unwrap_or_else(|| terminators.to_vec()),
+            brackets: config.brackets.clone().unwrap_or_else(|| brackets.to_vec()),
+            newlines: config.newlines,
+            ellipsis: config.ellipsis,
+            min_length: config.min_length,
+            max_length: config.max_length,
+            join_lines_with_space: language.has_word_spacing()
+        }
+    }
+
+    // How many characters of ellipsis start at the given position, if any.
+    fn ellipsis_length(chars: &[char], start: usize) -> usize {
+        let run = |c: &[char]| chars[start..].iter().take_while(|x| c.contains(x)).count();
+
+        match run(&['…', '‥']) {
+            0 => match run(&['.']) {
+                // A single full stop is just a full stop.
+                dots if dots >= 2 => dots,
+                _ => 0
+            },
+            length => length
+        }
+    }
+
+    // Split up a text into sentences.
+    pub fn segment(&self, text: &str) -> Segmentation {
+        let chars: Vec<char> = text.chars().collect();
+        let mut segmentation = Segmentation::default();
+        let mut current = String::new();
+        // The closing brackets we're waiting for, innermost last.
+        let mut open_brackets: Vec<char> = Vec::new();
+
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+
+            if c == '\n' || c == '\r' {
+                let mut end = i;
+                let mut newlines = 0;
+                while end < chars.len() && chars[end].is_whitespace() {
+                    if chars[end] == '\n' {
+                        newlines += 1;
+                    }
+                    end += 1;
+                }
+
+                let breaks = match self.newlines {
+                    NewlineMode::Hard => true,
+                    NewlineMode::Paragraph => newlines >= 2
+                };
+                if breaks {
+                    // A bracket that's still open by the end of the line or paragraph was never going to be closed.
+                    open_brackets.clear();
+                    self.finish_sentence(&mut current, &mut segmentation);
+                } else if self.join_lines_with_space {
+                    current.push(' ');
+                }
+
+                i = end;
+                continue;
+            }
+
+            let ellipsis = Self::ellipsis_length(&chars, i);
+            if ellipsis > 0 {
+                current.extend(&chars[i..i + ellipsis]);
+                i += ellipsis;
+
+                if self.ellipsis == EllipsisMode::Terminate && open_brackets.is_empty() {
+                    self.finish_sentence(&mut current, &mut segmentation);
+                }
+                continue;
+            }
+
+            current.push(c);
+            i += 1;
+
+            if let Some(position) = open_brackets.iter().rposition(|close| *close == c) {
+                // Closing a bracket also closes anything left open inside it.
+                open_brackets.truncate(position);
+            }
+            else if let Some((_, close)) = self.brackets.iter().find(|(open, _)| *open == c) {
+                open_brackets.push(*close);
+            }
+            else if open_brackets.is_empty() && self.terminators.contains(&c) {
+                // A full stop in the middle of something like 3.5 or www.example.com doesn't end the sentence.
+                if c == '.' && chars.get(i).is_some_and(|next| !next.is_whitespace()) {
+                    continue;
+                }
+
+                // Keep runs like ！？ together.
+                while i < chars.len() && self.terminators.contains(&chars[i]) {
+                    current.push(chars[i]);
+                    i += 1;
+                }
+
+                self.finish_sentence(&mut current, &mut segmentation);
+            }
+            // Any other closing bracket doesn't have an opening one, so there's nothing to close.
+        }
+
+        // Whatever is left over is a sentence too, even without a terminator.
+        self.finish_sentence(&mut current, &mut segmentation);
+
+        segmentation
+    }
+
+    // Whether a bit of text finishes with the end of a sentence, e.g. a line of subtitles that doesn't carry on onto the next one.
+    pub fn ends_sentence(&self, text: &str) -> bool {
+        let closers: Vec<char> = self.brackets.iter().map(|(_, close)| *close).collect();
+        let text = text.trim_end().trim_end_matches(|c| closers.contains(&c));
+
+        if text.ends_with(['…', '‥']) || text.ends_with("..") {
+            return self.ellipsis == EllipsisMode::Terminate;
+        }
+        text.chars().last().is_some_and(|last| self.terminators.contains(&last))
+    }
+
+    fn finish_sentence(&self, current: &mut String, segmentation: &mut Segmentation) {
+        let sentence = current.trim().to_string();
+        current.clear();
+
+        if sentence.is_empty() {
+            return;
+        }
+
+        let length = sentence.chars().count();
+        if length < self.min_length {
+            segmentation.rejected.push(RejectedSentence { text: sentence, reason: RejectReason::TooShort });
+        }
+        else if self.max_length.is_some_and(|max_length| length > max_length) {
+            segmentation.rejected.push(RejectedSentence { text: sentence, reason: RejectReason::TooLong });
+        }
+        else {
+            segmentation.sentences.push(sentence);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(language: Language, config: SegmenterConfig, text: &str) -> Segmentation {
+        Segmenter::new(language, &config).segment(text)
+    }
+
+    #[test]
+    fn splits_on_terminators() {
+        let segmentation = segment(Language::Japanese, SegmenterConfig::default(), "猫が好き。犬も好き！本当？");
+        assert_eq!(segmentation.sentences, ["猫が好き。", "犬も好き！", "本当？"]);
+    }
+
+    #[test]
+    fn keeps_runs_of_terminators_together() {
+        let segmentation = segment(Language::Japanese, SegmenterConfig::default(), "本当！？そうだよ。");
+        assert_eq!(segmentation.sentences, ["本当！？", "そうだよ。"]);
+    }
+
+    #[test]
+    fn carries_on_inside_brackets() {
+        let segmentation = segment(Language::Japanese, SegmenterConfig::default(), "「行こう。早く！」と言った。次だ。");
+        assert_eq!(segmentation.sentences, ["「行こう。早く！」と言った。", "次だ。"]);
+    }
+
+    #[test]
+    fn an_unclosed_bracket_ends_with_the_line() {
+        let segmentation = segment(Language::Japanese, SegmenterConfig::default(), "「行こう。\n次だ。");
+        assert_eq!(segmentation.sentences, ["「行こう。", "次だ。"]);
+    }
+
+    #[test]
+    fn full_stops_inside_words_dont_end_sentences() {
+        let segmentation = segment(Language::English, SegmenterConfig::default(), "It costs 3.50 at example.com. Cheap!");
+        assert_eq!(segmentation.sentences, ["It costs 3.50 at example.com.", "Cheap!"]);
+    }
+
+    #[test]
+    fn paragraph_mode_joins_wrapped_lines() {
+        let config = SegmenterConfig { newlines: NewlineMode::Paragraph, ..SegmenterConfig::default() };
+
+        let english = segment(Language::English, config.clone(), "This sentence was\nwrapped\n\nNext");
+        assert_eq!(english.sentences, ["This sentence was wrapped", "Next"]);
+
+        let japanese = segment(Language::Japanese, config, "折り返された\n文章\n\n次");
+        assert_eq!(japanese.sentences, ["折り返された文章", "次"]);
+    }
+
+    #[test]
+    fn ellipses_continue_or_terminate() {
+        let text = "それは……わからない。";
+
+        let continued = segment(Language::Japanese, SegmenterConfig::default(), text);
+        assert_eq!(continued.sentences, ["それは……わからない。"]);
+
+        let config = SegmenterConfig { ellipsis: EllipsisMode::Terminate, ..SegmenterConfig::default() };
+        let terminated = segment(Language::Japanese, config, text);
+        assert_eq!(terminated.sentences, ["それは……", "わからない。"]);
+    }
+
+    #[test]
+    fn rejects_sentences_outside_the_length_limits() {
+        let config = SegmenterConfig { min_length: 3, max_length: Some(6), ..SegmenterConfig::default() };
+        let segmentation = segment(Language::Japanese, config, "え。猫が好き。猫も犬も大好きです。");
+
+        assert_eq!(segmentation.sentences, ["猫が好き。"]);
+        assert_eq!(segmentation.rejected.len(), 2);
+        assert_eq!(segmentation.rejected[0].text, "え。");
+        assert!(matches!(segmentation.rejected[0].reason, RejectReason::TooShort));
+        assert_eq!(segmentation.rejected[1].text, "猫も犬も大好きです。");
+        assert!(matches!(segmentation.rejected[1].reason, RejectReason::TooLong));
+    }
+
+    #[test]
+    fn length_limits_are_inclusive() {
+        let config = SegmenterConfig { min_length: 2, max_length: Some(3), ..SegmenterConfig::default() };
+        let segmentation = segment(Language::Japanese, config, "猫。猫だ。");
+
+        assert_eq!(segmentation.sentences, ["猫。", "猫だ。"]);
+        assert!(segmentation.rejected.is_empty());
+    }
+
+    #[test]
+    fn custom_terminators_and_brackets() {
+        let config = SegmenterConfig {
+            terminators: Some(vec!['|']),
+            brackets: Some(vec![('<', '>')]),
+            ..SegmenterConfig::default()
+        };
+        let segmentation = segment(Language::Japanese, config, "一。<二|三>|四");
+
+        assert_eq!(segmentation.sentences, ["一。<二|三>|", "四"]);
+    }
+
+    #[test]
+    fn ends_sentence() {
+        let segmenter = Segmenter::new(Language::Japanese, &SegmenterConfig::default());
+        assert!(segmenter.ends_sentence("行こう。"));
+        assert!(segmenter.ends_sentence("「行こう！」 "));
+        assert!(!segmenter.ends_sentence("行こう"));
+        assert!(!segmenter.ends_sentence("それは……"));
+
+        let config = SegmenterConfig { ellipsis: EllipsisMode::Terminate, ..SegmenterConfig::default() };
+        assert!(Segmenter::new(Language::Japanese, &config).ends_sentence("それは……"));
+    }
+}
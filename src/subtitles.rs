@@ -0,0 +1,432 @@
+use std::path::Path;
+
+use crate::knowledge::{Knowledge, KnowledgeResult, KnowledgeError, AddTextReport, DocumentPart, SentenceTiming};
+use crate::language::Language;
+use crate::segmenter::Segmenter;
+
+// Lines further apart than this are never part of the same sentence.
+const MAX_MERGE_GAP_MS: i64 = 2000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    // Advanced SubStation Alpha, and the older SubStation Alpha which is close enough.
+    Ass,
+    WebVtt
+}
+
+impl SubtitleFormat {
+    // Work out the format from the file's extension, or from what's in it if that doesn't say.
+    pub fn detect(path: Option<&Path>, contents: &str) -> Self {
+        let extension = path
+            .and_then(|path| path.extension())
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("srt") => Self::Srt,
+            Some("ass" | "ssa") => Self::Ass,
+            Some("vtt") => Self::WebVtt,
+            _ if contents.trim_start_matches('\u{FEFF}').starts_with("WEBVTT") => Self::WebVtt,
+            _ if contents.contains("[Script Info]") || contents.contains("[Events]") => Self::Ass,
+            _ => Self::Srt
+        }
+    }
+}
+
+// A single subtitle, with its styling taken out.
+#[derive(Debug)]
+pub struct Cue {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub lines: Vec<String>
+}
+
+pub fn parse(contents: &str, format: SubtitleFormat) -> Result<Vec<Cue>, String> {
+    let contents = contents.trim_start_matches('\u{FEFF}').replace("\r\n", "\n").replace('\r', "\n");
+    let mut cues = match format {
+        SubtitleFormat::Srt | SubtitleFormat::WebVtt => parse_blocks(&contents),
+        SubtitleFormat::Ass => parse_ass(&contents)?
+    };
+
+    cues.retain(|cue| !cue.lines.is_empty());
+    if cues.is_empty() {
+        return Err("There aren't any subtitles in the file".to_string());
+    }
+    cues.sort_by_key(|cue| cue.start_ms);
+
+    // The same line is often in a file more than once, e.g. for an outline or a shadow.
+    cues.dedup_by(|next, cue| next.lines == cue.lines && next.start_ms <= cue.end_ms);
+
+    Ok(cues)
+}
+
+// SRT and WebVTT are both blocks of a timing line followed by the text, separated by blank lines.
+// Anything before the timing line is a cue number or identifier, and blocks without one are headers, notes or styles.
+fn parse_blocks(contents: &str) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    // A blank line is often left with a few spaces or tabs on it, which still ends the block.
+    let all_lines: Vec<&str> = contents.lines().collect();
+    for lines in all_lines.split(|line| line.trim().is_empty()) {
+        let Some(timing_line) = lines.iter().position(|line| line.contains("-->")) else {
+            continue;
+        };
+
+        let (start, end) = lines[timing_line].split_once("-->").unwrap_or_default();
+        // WebVTT can have settings like "align:start" after the end time.
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let (Some(start_ms), Some(end_ms)) = (parse_timestamp(start), parse_timestamp(end)) else {
+            continue;
+        };
+
+        cues.push(Cue {
+            start_ms,
+            end_ms,
+            lines: lines[timing_line + 1..].iter()
+                .map(|line| strip_tags(line))
+                .filter(|line| !line.is_empty())
+                .collect()
+        });
+    }
+    cues
+}
+
+fn parse_ass(contents: &str) -> Result<Vec<Cue>, String> {
+    let mut in_events = false;
+    let mut format: Vec<String> = Vec::new();
+    let mut cues = Vec::new();
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[Events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+
+        let Some((kind, fields)) = line.split_once(':') else {
+            continue;
+        };
+        match kind.trim() {
+            "Format" => format = fields.split(',').map(|field| field.trim().to_lowercase()).collect(),
+            // Comment lines are left in by people editing the subtitles and aren't shown.
+            "Dialogue" => {
+                if format.is_empty() {
+                    return Err("The events don't have a format line".to_string());
+                }
+
+                // The text is always last and can have commas in it.
+                let fields: Vec<&str> = fields.splitn(format.len(), ',').collect();
+                let field = |name: &str| format.iter().position(|field| field == name).and_then(|i| fields.get(i)).copied();
+
+                let (Some(start_ms), Some(end_ms)) = (field("start").and_then(parse_timestamp), field("end").and_then(parse_timestamp)) else {
+                    continue;
+                };
+                let text = field("text").unwrap_or_default();
+
+                cues.push(Cue {
+                    start_ms,
+                    end_ms,
+                    lines: strip_ass_overrides(text).split('\n')
+                        .map(|line| line.trim().to_string())
+                        .filter(|line| !line.is_empty())
+                        .collect()
+                });
+            },
+            _ => {}
+        }
+    }
+
+    Ok(cues)
+}
+
+// Timestamps like 01:02:03,456 (SRT), 02:03.456 (WebVTT) or 1:02:03.45 (ASS).
+fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    let timestamp = timestamp.trim();
+    let (time, fraction) = timestamp.split_once([',', '.']).unwrap_or((timestamp, "0"));
+
+    let mut seconds: i64 = 0;
+    for part in time.split(':') {
+        seconds = seconds * 60 + part.trim().parse::<i64>().ok()?;
+    }
+
+    // The fraction is in however many digits it's written with, so .5, .50 and .500 are all half a second.
+    let fraction: String = fraction.chars().take(3).collect();
+    let millis = fraction.parse::<i64>().ok()? * 10_i64.pow(3 - fraction.len() as u32);
+
+    Some(seconds * 1000 + millis)
+}
+
+// Take the HTML-like tags out of an SRT or WebVTT line, along with any furigana and ASS override blocks that have been copied into it.
+fn strip_tags(line: &str) -> String {
+    let mut text = String::new();
+    let mut rest = line;
+    let mut skipping: Option<&str> = None;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                let tag = rest[1..end].trim().to_lowercase();
+                let name = tag.split(|c: char| c.is_whitespace() || c == '.').next().unwrap_or_default();
+                match (skipping, name) {
+                    (None, "rt" | "rp") => skipping = if name == "rt" { Some("/rt") } else { Some("/rp") },
+                    (Some(closing), name) if name == closing => skipping = None,
+                    _ => {}
+                }
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        if c == '{' && rest[1..].starts_with('\\') {
+            if let Some(end) = rest.find('}') {
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+
+        if skipping.is_none() {
+            text.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&lrm;", "")
+        .replace("&rlm;", "")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+// Take the {\...} override blocks out of an ASS line, and turn its escapes into the characters they stand for.
+fn strip_ass_overrides(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = text;
+    // Text in drawing mode is a list of vector drawing commands, not something anyone says.
+    let mut drawing = false;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '{' {
+            if let Some(end) = rest.find('}') {
+                let block = &rest[1..end];
+                if let Some(p) = block.rfind("\\p") {
+                    let level: String = block[p + 2..].chars().take_while(char::is_ascii_digit).collect();
+                    if !level.is_empty() {
+                        drawing = level != "0";
+                    }
+                }
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+
+        if !drawing {
+            match (c, rest[c.len_utf8()..].chars().next()) {
+                ('\\', Some('N' | 'n')) => {
+                    stripped.push('\n');
+                    rest = &rest[2..];
+                    continue;
+                },
+                ('\\', Some('h')) => {
+                    stripped.push(' ');
+                    rest = &rest[2..];
+                    continue;
+                },
+                _ => stripped.push(c)
+            }
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+
+    stripped
+}
+
+// Turn cues into parts of a document, one for each sentence as best we can tell.
+// A line that's been split over several cues is put back together, and a cue with a line from each of two speakers is split up.
+pub fn to_document(cues: &[Cue], segmenter: &Segmenter, language: Language) -> Vec<DocumentPart> {
+    let separator = if language.has_word_spacing() { " " } else { "" };
+
+    // A line like "- Where are you going?" is someone talking, as opposed to the rest of the previous line.
+    let utterances = |cue: &Cue| -> Vec<String> {
+        let mut utterances: Vec<Vec<&str>> = Vec::new();
+        for line in &cue.lines {
+            match line.strip_prefix(['-', '‐', '－']) {
+                Some(speech) => utterances.push(vec![speech.trim()]),
+                None => match utterances.last_mut() {
+                    Some(utterance) => utterance.push(line),
+                    None => utterances.push(vec![line])
+                }
+            }
+        }
+        utterances.into_iter().map(|lines| lines.join(separator)).collect()
+    };
+
+    // Plenty of subtitles (most Japanese ones) don't use full stops at all, in which case every cue is a sentence of its own.
+    // Otherwise a cue that doesn't end with one carries on into the next cue.
+    let punctuated = cues.iter()
+        .filter(|cue| utterances(cue).last().is_some_and(|utterance| segmenter.ends_sentence(utterance)))
+        .count() * 2 >= cues.len();
+
+    let mut parts = Vec::new();
+    let mut pending: Option<DocumentPart> = None;
+    for (i, cue) in cues.iter().enumerate() {
+        for (j, utterance) in utterances(cue).into_iter().enumerate() {
+            if j > 0 {
+                parts.extend(pending.take());
+            }
+
+            match &mut pending {
+                Some(part) => {
+                    part.text.push_str(separator);
+                    part.text.push_str(&utterance);
+                    if let Some(timing) = &mut part.timing {
+                        timing.end_ms = timing.end_ms.max(cue.end_ms);
+                    }
+                },
+                None => pending = Some(DocumentPart {
+                    text: utterance,
+                    timing: Some(SentenceTiming { start_ms: cue.start_ms, end_ms: cue.end_ms }),
+                    ruby: Vec::new()
+                })
+            }
+        }
+
+        let Some(part) = &pending else {
+            continue;
+        };
+        let unfinished = part.text.trim_end().ends_with([',', '、', '，'])
+            || (punctuated && !segmenter.ends_sentence(&part.text));
+        let next_is_close = cues.get(i + 1).is_some_and(|next| next.start_ms - cue.end_ms <= MAX_MERGE_GAP_MS);
+        if !(unfinished && next_is_close) {
+            parts.extend(pending.take());
+        }
+    }
+    parts.extend(pending);
+
+    parts
+}
+
+// Add the sentences from a subtitle file, with the time each one is said.
+pub async fn import_subtitles(knowledge: &mut Knowledge, contents: &str, format: SubtitleFormat,
+                              source: &str, language: Language) -> KnowledgeResult<AddTextReport> {
+    let cues = parse(contents, format).map_err(KnowledgeError::InvalidImport)?;
+    let parts = to_document(&cues, &knowledge.segmenter(language), language);
+    log::info!("Importing {} lines of subtitles from {}", parts.len(), source);
+
+    knowledge.add_document(&parts, source, language).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("01:02:03,456"), Some(3_723_456));
+        assert_eq!(parse_timestamp("02:03.456"), Some(123_456));
+        assert_eq!(parse_timestamp("1:02:03.45"), Some(3_723_450));
+        assert_eq!(parse_timestamp(" 0:00:01.5 "), Some(1_500));
+        assert_eq!(parse_timestamp("00:00:07"), Some(7_000));
+    }
+
+    #[test]
+    fn rejects_broken_timestamps() {
+        assert_eq!(parse_timestamp(""), None);
+        assert_eq!(parse_timestamp("aa:bb:cc,ddd"), None);
+        assert_eq!(parse_timestamp("00:01:02,"), None);
+    }
+
+    #[test]
+    fn parses_srt() {
+        let srt = "1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>こんにちは</i>\r\n世界\r\n\r\n2\r\n00:00:03,000 --> 00:00:04,000\r\nさようなら\r\n";
+        let cues = parse(srt, SubtitleFormat::Srt).unwrap();
+
+        assert_eq!(cues.len(), 2);
+        assert_eq!((cues[0].start_ms, cues[0].end_ms), (1_000, 2_500));
+        assert_eq!(cues[0].lines, ["こんにちは", "世界"]);
+        assert_eq!(cues[1].lines, ["さようなら"]);
+    }
+
+    #[test]
+    fn parses_webvtt() {
+        let vtt = "WEBVTT\n\nNOTE a comment\n\nintro\n00:01.000 --> 00:02.000 align:start position:10%\n<c.yellow>はい</c>\n";
+        let cues = parse(vtt, SubtitleFormat::WebVtt).unwrap();
+
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start_ms, cues[0].end_ms), (1_000, 2_000));
+        assert_eq!(cues[0].lines, ["はい"]);
+    }
+
+    #[test]
+    fn blocks_can_be_separated_by_whitespace() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nはい\n \n2\n00:00:03,000 --> 00:00:04,000\nいいえ\n\t\n3\n00:00:05,000 --> 00:00:06,000\nどうも\n";
+        let cues = parse(srt, SubtitleFormat::Srt).unwrap();
+
+        assert_eq!(cues.len(), 3);
+        assert_eq!(cues[0].lines, ["はい"]);
+        assert_eq!((cues[1].start_ms, cues[1].end_ms), (3_000, 4_000));
+        assert_eq!(cues[1].lines, ["いいえ"]);
+        assert_eq!(cues[2].lines, ["どうも"]);
+    }
+
+    #[test]
+    fn drops_duplicate_cues() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nはい\n\n2\n00:00:01,000 --> 00:00:02,000\nはい\n";
+        assert_eq!(parse(srt, SubtitleFormat::Srt).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn empty_files_are_an_error() {
+        assert!(parse("", SubtitleFormat::Srt).is_err());
+        assert!(parse("WEBVTT\n\n", SubtitleFormat::WebVtt).is_err());
+    }
+
+    #[test]
+    fn parses_ass() {
+        let ass = "[Script Info]\nTitle: test\n\n[Events]\n\
+            Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+            Comment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,しない\n\
+            Dialogue: 0,0:00:01.50,0:00:03.00,Default,,0,0,0,,{\\an8}あれ、\\Nそれ{\\i1}は{\\i0}何？\n";
+        let cues = parse(ass, SubtitleFormat::Ass).unwrap();
+
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start_ms, cues[0].end_ms), (1_500, 3_000));
+        assert_eq!(cues[0].lines, ["あれ、", "それは何？"]);
+    }
+
+    #[test]
+    fn ass_needs_a_format_line() {
+        let ass = "[Events]\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,はい\n";
+        assert!(parse(ass, SubtitleFormat::Ass).is_err());
+    }
+
+    #[test]
+    fn strips_ass_overrides() {
+        assert_eq!(strip_ass_overrides("{\\pos(10,10)\\c&HFFFFFF&}はい"), "はい");
+        assert_eq!(strip_ass_overrides("一\\N二\\n三"), "一\n二\n三");
+        assert_eq!(strip_ass_overrides("a\\hb"), "a b");
+        // Drawings are vector commands rather than text, up until drawing mode is turned off.
+        assert_eq!(strip_ass_overrides("{\\p1}m 0 0 l 100 0 100 100{\\p0}はい"), "はい");
+        // A brace that isn't closed is just a brace.
+        assert_eq!(strip_ass_overrides("{はい"), "{はい");
+    }
+
+    #[test]
+    fn strips_tags_and_ruby() {
+        assert_eq!(strip_tags("<b>太字</b>と<font color=\"red\">赤</font>"), "太字と赤");
+        assert_eq!(strip_tags("<ruby>漢字<rp>(</rp><rt>かんじ</rt><rp>)</rp></ruby>"), "漢字");
+        assert_eq!(strip_tags("{\\an8}上"), "上");
+        assert_eq!(strip_tags("&lt;3 &amp; more"), "<3 & more");
+    }
+
+    #[test]
+    fn detects_the_format() {
+        assert_eq!(SubtitleFormat::detect(Some(Path::new("a.ASS")), ""), SubtitleFormat::Ass);
+        assert_eq!(SubtitleFormat::detect(Some(Path::new("a.vtt")), ""), SubtitleFormat::WebVtt);
+        assert_eq!(SubtitleFormat::detect(None, "\u{FEFF}WEBVTT\n"), SubtitleFormat::WebVtt);
+        assert_eq!(SubtitleFormat::detect(None, "[Script Info]\n"), SubtitleFormat::Ass);
+        assert_eq!(SubtitleFormat::detect(Some(Path::new("a.txt")), "1\n"), SubtitleFormat::Srt);
+    }
+}
@@ -0,0 +1,139 @@
+use std::{collections::HashMap, io::BufRead};
+
+use crate::knowledge::{Knowledge, KnowledgeResult, KnowledgeError, AddTextReport};
+use crate::language::Language;
+
+// A sentence from a Tatoeba dump (https://tatoeba.org/en/downloads) with a translation of it.
+pub struct TatoebaSentence {
+    pub id: i64,
+    pub text: String,
+    pub translation: String
+}
+
+// Read sentences.csv and links.csv, keeping the sentences in a language that have a translation in the other.
+// Both are tab separated, sentences.csv is id, language, text and links.csv is pairs of ids of sentences that translate each other.
+// The dumps are big, so both are streamed a line at a time and only the sentences in the two languages are kept along the way.
+pub fn read(sentences: impl BufRead, links: impl BufRead, language: &str, translation_language: &str) -> Result<Vec<TatoebaSentence>, String> {
+    let mut originals: HashMap<i64, String> = HashMap::new();
+    let mut translations: HashMap<i64, String> = HashMap::new();
+    for line in sentences.lines() {
+        let line = line.map_err(|e| format!("Couldn't read the sentences: {}", e))?;
+        let mut fields = line.splitn(3, '\t');
+        let (Some(id), Some(lang), Some(text)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        if lang != language && lang != translation_language {
+            continue;
+        }
+        let Ok(id) = id.parse::<i64>() else {
+            continue;
+        };
+
+        match lang == language {
+            true => originals.insert(id, text.to_string()),
+            false => translations.insert(id, text.to_string())
+        };
+    }
+
+    let mut paired = Vec::new();
+    for line in links.lines() {
+        let line = line.map_err(|e| format!("Couldn't read the links: {}", e))?;
+        let Some((from, to)) = line.split_once('\t') else {
+            continue;
+        };
+        let (Ok(from), Ok(to)) = (from.trim().parse::<i64>(), to.trim().parse::<i64>()) else {
+            continue;
+        };
+
+        // Sentences often have several translations, the first one is as good as any.
+        let Some(translation) = translations.get(&to) else {
+            continue;
+        };
+        if let Some(text) = originals.remove(&from) {
+            paired.push(TatoebaSentence { id: from, text, translation: translation.clone() });
+        }
+    }
+
+    // Keep them in the order they were added to Tatoeba rather than the order of the links.
+    paired.sort_by_key(|sentence| sentence.id);
+    Ok(paired)
+}
+
+// Add the sentences in the language that have a translation, skipping any we already have.
+pub async fn import_tatoeba(knowledge: &mut Knowledge, sentences: impl BufRead, links: impl BufRead,
+                            language: Language, translation_language: &str) -> KnowledgeResult<AddTextReport> {
+    let sentences = read(sentences, links, language.iso_639_3(), translation_language).map_err(KnowledgeError::InvalidImport)?;
+    log::info!("Found {} {} sentences with a translation", sentences.len(), language.name());
+
+    let mut report = AddTextReport::default();
+    for (i, sentence) in sentences.iter().enumerate() {
+        let source = format!("Tatoeba #{}", sentence.id);
+        if knowledge.add_translated_sentence(&sentence.text, &sentence.translation, &source, language).await? {
+            report.sentences_added += 1;
+        }
+
+        if (i + 1) % 1000 == 0 {
+            log::info!("Imported {} of {} sentences", i + 1, sentences.len());
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_str(sentences: &str, links: &str) -> Vec<(i64, String, String)> {
+        read(sentences.as_bytes(), links.as_bytes(), "jpn", "eng").unwrap()
+            .into_iter()
+            .map(|sentence| (sentence.id, sentence.text, sentence.translation))
+            .collect()
+    }
+
+    #[test]
+    fn pairs_sentences_with_translations() {
+        let sentences = "1\tjpn\t猫だ。\n2\teng\tIt's a cat.\n3\tjpn\t犬だ。\n4\teng\tIt's a dog.\n";
+        let links = "1\t2\n2\t1\n3\t4\n4\t3\n";
+
+        assert_eq!(read_str(sentences, links), [
+            (1, "猫だ。".to_string(), "It's a cat.".to_string()),
+            (3, "犬だ。".to_string(), "It's a dog.".to_string())
+        ]);
+    }
+
+    #[test]
+    fn keeps_the_first_translation() {
+        let sentences = "1\tjpn\t猫だ。\n2\teng\tIt's a cat.\n3\teng\tThat's a cat.\n";
+        let links = "1\t3\n1\t2\n";
+
+        assert_eq!(read_str(sentences, links), [(1, "猫だ。".to_string(), "That's a cat.".to_string())]);
+    }
+
+    #[test]
+    fn only_uses_translations_in_the_translation_language() {
+        let sentences = "1\tjpn\t猫だ。\n2\tfra\tC'est un chat.\n3\tjpn\tそうだ。\n";
+        let links = "1\t2\n1\t3\n3\t1\n";
+
+        assert!(read_str(sentences, links).is_empty());
+    }
+
+    #[test]
+    fn sentences_keep_their_order_and_tabs_in_the_text() {
+        let sentences = "5\tjpn\tあ\tい\n1\tjpn\tう\n2\teng\tA\n";
+        let links = "5\t2\n1\t2\n";
+
+        assert_eq!(read_str(sentences, links), [
+            (1, "う".to_string(), "A".to_string()),
+            (5, "あ\tい".to_string(), "A".to_string())
+        ]);
+    }
+
+    #[test]
+    fn skips_broken_lines() {
+        let sentences = "x\tjpn\t猫だ。\n1\tjpn\n2\tjpn\t犬だ。\n3\teng\tA dog.\n";
+        let links = "nonsense\n2\t\n2 3\n2\t3\n";
+
+        assert_eq!(read_str(sentences, links), [(2, "犬だ。".to_string(), "A dog.".to_string())]);
+    }
+}
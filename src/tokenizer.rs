@@ -0,0 +1,771 @@
+use std::{
+    collections::HashMap,
+    process::{Command, Stdio, Child, ChildStdin},
+    io::{self, Write, BufRead, BufReader},
+    sync::{Arc, Mutex, mpsc::{self, Receiver, RecvTimeoutError}, atomic::{AtomicUsize, Ordering}},
+    thread,
+    time::{Duration, Instant}
+};
+
+use clap::ValueEnum;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::knowledge::{KnowledgeError, KnowledgeResult};
+use crate::language::Language;
+
+// A single morpheme as it appears in a sentence, along with everything the tokenizer could tell us about it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Morpheme {
+    // The text as it appears in the sentence.
+    pub surface: String,
+    // How the surface text is read.
+    pub reading: String,
+    // The de-conjugated form, this is what we learn as a word.
+    pub dictionary_form: String,
+    pub pos: String,
+    pub sub_pos: String,
+    pub conjugation_type: String,
+    pub conjugation_form: String,
+    // Extra space separated key:value info, e.g. jumanpp's "代表表記:食べる/たべる カテゴリ:人工物-食べ物"
+    pub semantic_info: String,
+    // Whether we made the representative form in the semantic info up to carry a reading, rather than the tokenizer giving it.
+    pub synthetic_representative: bool,
+    // Where the surface text starts in the sentence, in characters.
+    pub offset: usize
+}
+
+impl Morpheme {
+    // A morpheme that the user has told us about rather than the tokenizer, e.g. from the user dictionary.
+    // The representative form is filled in so that the reading given is used as-is for the word, but it's not a lemma group.
+    pub fn user_defined(surface: &str, dictionary_form: &str, reading: &str, pos: PartOfSpeech, offset: usize) -> Self {
+        let (pos_tag, sub_pos_tag) = pos.tags();
+        Self {
+            surface: surface.to_string(),
+            reading: reading.to_string(),
+            dictionary_form: dictionary_form.to_string(),
+            pos: pos_tag.to_string(),
+            sub_pos: sub_pos_tag.to_string(),
+            conjugation_type: "*".to_string(),
+            conjugation_form: "*".to_string(),
+            semantic_info: format!("代表表記:{}/{}", dictionary_form, reading),
+            synthetic_representative: true,
+            offset
+        }
+    }
+
+    // Look up a value in the semantic info by key.
+    pub fn semantic_value(&self, key: &str) -> Option<&str> {
+        self.semantic_info
+            .split(' ')
+            .find_map(|item| item.strip_prefix(key)?.strip_prefix(':'))
+    }
+
+    // The representative form jumanpp gives for the word, e.g. 分かる/わかる for all of わかる, 分かる and 解る.
+    // Some of these have a trailing letter to mark them as derived forms (e.g. 走り/はしりv), drop that.
+    // Words that are written in letters anyway (e.g. ＡＢＣ/ＡＢＣ) are left alone.
+    pub fn representative_form(&self) -> Option<String> {
+        self.semantic_value("代表表記")
+            .map(|rep| {
+                let trimmed = rep.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+                match trimmed.ends_with(|c: char| c.is_ascii()) {
+                    true => rep.to_string(),
+                    false => trimmed.to_string()
+                }
+            })
+            .filter(|rep| rep.contains('/'))
+    }
+
+    // The representative form to group the word's spellings by, only if the tokenizer gave it.
+    // One we made up just has the word's own spelling, so it would group words that only share a spelling and reading.
+    pub fn lemma_group(&self) -> Option<String> {
+        self.representative_form().filter(|_| !self.synthetic_representative)
+    }
+
+    // The reading of the dictionary form in hiragana.
+    // Tokenizers give us the reading of the surface form, which for conjugated words isn't what we want.
+    pub fn lemma_reading(&self) -> String {
+        // jumanpp tells us directly in the representative form, e.g. 代表表記:食べる/たべる
+        if let Some((_, reading)) = self.representative_form().as_deref().and_then(|rep| rep.split_once('/')) {
+            return to_hiragana(reading);
+        }
+
+        let reading = to_hiragana(&self.reading);
+        if self.surface == self.dictionary_form {
+            return reading;
+        }
+
+        // Otherwise swap the conjugated ending for the dictionary form's ending.
+        // e.g. 食べた (たべた) -> 食べる: the shared stem is 食べ, the surface ending た is dropped from
+        // the reading and the dictionary form's ending る is added on giving たべる.
+        let shared = self.surface.chars()
+            .zip(self.dictionary_form.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let surface_ending = to_hiragana(&self.surface.chars().skip(shared).collect::<String>());
+        let lemma_ending: String = self.dictionary_form.chars().skip(shared).collect();
+
+        match reading.strip_suffix(surface_ending.as_str()) {
+            Some(stem) if lemma_ending.chars().all(is_kana) => stem.to_string() + &to_hiragana(&lemma_ending),
+            _ => reading
+        }
+    }
+
+    // Work out the coarse part of speech from the tokenizer's own tags.
+    // jumanpp uses the JUMAN tag set and lindera uses IPADIC's, they overlap a lot so handle both here.
+    pub fn part_of_speech(&self) -> PartOfSpeech {
+        // Tokenizers don't always know what to do with half-width numbers and punctuation.
+        if self.surface.chars().all(|c| c.is_numeric()) {
+            return PartOfSpeech::Numeral;
+        }
+        if self.surface.chars().all(|c| c.is_ascii_punctuation()) {
+            return PartOfSpeech::Symbol;
+        }
+
+        match (self.pos.as_str(), self.sub_pos.as_str()) {
+            ("名詞", "数詞") | ("名詞", "数") => PartOfSpeech::Numeral,
+            ("名詞", _) => PartOfSpeech::Noun,
+            ("動詞", _) => PartOfSpeech::Verb,
+            ("形容詞", _) => PartOfSpeech::Adjective,
+            ("副詞", _) => PartOfSpeech::Adverb,
+            ("連体詞", _) | ("指示詞", _) => PartOfSpeech::Adnominal,
+            ("助詞", _) => PartOfSpeech::Particle,
+            // JUMAN calls だ/です a 判定詞 where IPADIC calls them auxiliary verbs, treat them the same.
+            ("助動詞", _) | ("判定詞", _) => PartOfSpeech::AuxiliaryVerb,
+            ("接続詞", _) => PartOfSpeech::Conjunction,
+            ("感動詞", _) | ("フィラー", _) => PartOfSpeech::Interjection,
+            ("接頭辞", _) | ("接頭詞", _) => PartOfSpeech::Prefix,
+            ("接尾辞", _) => PartOfSpeech::Suffix,
+            ("特殊", _) | ("記号", _) => PartOfSpeech::Symbol,
+            _ => PartOfSpeech::Other
+        }
+    }
+}
+
+pub fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{309F}' | '\u{30A0}'..='\u{30FF}')
+}
+
+// Convert any katakana in the text to hiragana, so that readings from different tokenizers can be compared.
+pub fn to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\u{30A1}'..='\u{30F6}' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c
+        })
+        .collect()
+}
+
+// A tokenizer independent part of speech.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum PartOfSpeech {
+    Noun,
+    Verb,
+    Adjective,
+    Adverb,
+    Adnominal,
+    Particle,
+    AuxiliaryVerb,
+    Conjunction,
+    Interjection,
+    Prefix,
+    Suffix,
+    Symbol,
+    Numeral,
+    Other
+}
+
+impl PartOfSpeech {
+    pub const ALL: [PartOfSpeech; 14] = [
+        Self::Noun, Self::Verb, Self::Adjective, Self::Adverb, Self::Adnominal, Self::Particle, Self::AuxiliaryVerb,
+        Self::Conjunction, Self::Interjection, Self::Prefix, Self::Suffix, Self::Symbol, Self::Numeral, Self::Other
+    ];
+
+    // The parts of speech that aren't worth drilling by default.
+    pub const DEFAULT_EXCLUDED: [PartOfSpeech; 4] = [
+        Self::Particle, Self::AuxiliaryVerb, Self::Symbol, Self::Numeral
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|pos| pos.as_str() == name)
+    }
+
+    // JUMAN tags that part_of_speech() maps back to this.
+    pub fn tags(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::Noun => ("名詞", "普通名詞"),
+            Self::Verb => ("動詞", "*"),
+            Self::Adjective => ("形容詞", "*"),
+            Self::Adverb => ("副詞", "*"),
+            Self::Adnominal => ("連体詞", "*"),
+            Self::Particle => ("助詞", "*"),
+            Self::AuxiliaryVerb => ("助動詞", "*"),
+            Self::Conjunction => ("接続詞", "*"),
+            Self::Interjection => ("感動詞", "*"),
+            Self::Prefix => ("接頭辞", "*"),
+            Self::Suffix => ("接尾辞", "*"),
+            Self::Symbol => ("特殊", "*"),
+            Self::Numeral => ("名詞", "数詞"),
+            Self::Other => ("未定義語", "*")
+        }
+    }
+
+    // The name stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Noun => "noun",
+            Self::Verb => "verb",
+            Self::Adjective => "adjective",
+            Self::Adverb => "adverb",
+            Self::Adnominal => "adnominal",
+            Self::Particle => "particle",
+            Self::AuxiliaryVerb => "auxiliary_verb",
+            Self::Conjunction => "conjunction",
+            Self::Interjection => "interjection",
+            Self::Prefix => "prefix",
+            Self::Suffix => "suffix",
+            Self::Symbol => "symbol",
+            Self::Numeral => "numeral",
+            Self::Other => "other"
+        }
+    }
+}
+
+// Work out where each morpheme appears in the sentence.
+// Tokenizers drop whitespace so we can't just add up the lengths, search forwards for each surface instead.
+// If a surface can't be found (the tokenizer changed it somehow) it's given the position we've got up to.
+fn assign_offsets(sentence: &str, morphemes: &mut [Morpheme]) {
+    let mut byte_cursor = 0;
+    let mut char_cursor = 0;
+    for morpheme in morphemes {
+        match sentence[byte_cursor..].find(morpheme.surface.as_str()) {
+            Some(found) => {
+                let start_char = char_cursor + sentence[byte_cursor..byte_cursor + found].chars().count();
+                morpheme.offset = start_char;
+
+                byte_cursor += found + morpheme.surface.len();
+                char_cursor = start_char + morpheme.surface.chars().count();
+            },
+            None => morpheme.offset = char_cursor
+        }
+    }
+}
+
+// Something that can split a sentence up into the morphemes it contains.
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, sentence: &str) -> KnowledgeResult<Vec<Morpheme>>;
+
+    // Which tokenizer this is and which version of it, stored with each sentence so that we know
+    // which sentences need tokenizing again when either changes.
+    fn name(&self) -> &str;
+    fn version(&self) -> &str;
+}
+
+// The tokenizers that can be picked at startup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TokenizerKind {
+    // Calls out to an external jumanpp binary.
+    Jumanpp,
+    // Runs lindera in-process using the IPADIC dictionary.
+    Lindera
+}
+
+// Settings for the jumanpp worker pool.
+pub struct JumanppConfig {
+    // How many jumanpp processes to keep running.
+    pub workers: usize,
+    // How long to wait for jumanpp to tokenize a single sentence before giving up on it.
+    pub timeout: Duration
+}
+
+pub fn create_tokenizer(kind: TokenizerKind, jumanpp_config: JumanppConfig) -> KnowledgeResult<Arc<dyn Tokenizer>> {
+    Ok(match kind {
+        TokenizerKind::Jumanpp => Arc::new(JumanppTokenizer::new(jumanpp_config.workers, jumanpp_config.timeout)),
+        TokenizerKind::Lindera => Arc::new(LinderaTokenizer::new()?)
+    })
+}
+
+// A tokenizer for every language, Japanese uses whichever tokenizer was picked at startup.
+pub fn create_tokenizers(kind: TokenizerKind, jumanpp_config: JumanppConfig) -> KnowledgeResult<HashMap<Language, Arc<dyn Tokenizer>>> {
+    let mut tokenizers = HashMap::new();
+    tokenizers.insert(Language::Japanese, create_tokenizer(kind, jumanpp_config)?);
+
+    let word_boundary: Arc<dyn Tokenizer> = Arc::new(WordBoundaryTokenizer);
+    for language in Language::ALL.into_iter().filter(Language::has_word_spacing) {
+        tokenizers.insert(language, word_boundary.clone());
+    }
+
+    Ok(tokenizers)
+}
+
+// How much of jumanpp's stderr to hang on to for error messages.
+const MAX_STDERR_LEN: usize = 4096;
+
+// A jumanpp process that we keep alive and stream sentences through.
+// Loading the jumanpp model takes far longer than actually tokenizing a sentence so
+// spawning a new process for every sentence makes bulk imports painfully slow.
+struct JumanppWorker {
+    child: Child,
+    stdin: ChildStdin,
+    // Lines read from jumanpp's stdout by a background thread, so that we can wait on them with a timeout.
+    lines: Receiver<io::Result<String>>,
+    // The tail end of whatever jumanpp has written to stderr, so we can say why it died.
+    stderr: Arc<Mutex<String>>
+}
+
+impl JumanppWorker {
+    fn spawn() -> KnowledgeResult<Self> {
+        let mut child = Command::new("jumanpp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => KnowledgeError::TokenizerNotFound("jumanpp".to_string()),
+                _ => KnowledgeError::TokenizerError(format!("Couldn't start jumanpp: {}", e))
+            })?;
+
+        let stdin = child.stdin.take()
+            .ok_or_else(|| KnowledgeError::TokenizerError("jumanpp has no stdin".to_string()))?;
+        let stdout = child.stdout.take()
+            .ok_or_else(|| KnowledgeError::TokenizerError("jumanpp has no stdout".to_string()))?;
+        let child_stderr = child.stderr.take()
+            .ok_or_else(|| KnowledgeError::TokenizerError("jumanpp has no stderr".to_string()))?;
+
+        // Read stdout on another thread, the channel closes when jumanpp exits.
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // Same for stderr, only keeping the most recent output.
+        let stderr = Arc::new(Mutex::new(String::new()));
+        let stderr_writer = stderr.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(child_stderr).lines().map_while(Result::ok) {
+                let mut stderr = stderr_writer.lock().unwrap_or_else(|e| e.into_inner());
+                stderr.push_str(&line);
+                stderr.push('\n');
+
+                if stderr.len() > MAX_STDERR_LEN {
+                    let mut cut = stderr.len() - MAX_STDERR_LEN;
+                    while !stderr.is_char_boundary(cut) {
+                        cut += 1;
+                    }
+                    stderr.drain(..cut);
+                }
+            }
+        });
+
+        log::info!("Started jumanpp worker (pid {})", child.id());
+
+        Ok(Self {
+            child,
+            stdin,
+            lines,
+            stderr
+        })
+    }
+
+    // Send a single line to jumanpp and collect every output line up until the EOS marker.
+    fn analyze_line(&mut self, line: &str, timeout: Duration) -> Result<Vec<String>, JumanppWorkerError> {
+        writeln!(self.stdin, "{}", line).map_err(|_| JumanppWorkerError::Crashed)?;
+        self.stdin.flush().map_err(|_| JumanppWorkerError::Crashed)?;
+
+        let deadline = Instant::now() + timeout;
+        let mut output = Vec::new();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(remaining) {
+                Ok(Ok(line)) if line == "EOS" => return Ok(output),
+                Ok(Ok(line)) => output.push(line),
+                Ok(Err(e)) if e.kind() == io::ErrorKind::InvalidData => return Err(JumanppWorkerError::InvalidOutput(e.to_string())),
+                Ok(Err(_)) | Err(RecvTimeoutError::Disconnected) => return Err(JumanppWorkerError::Crashed),
+                Err(RecvTimeoutError::Timeout) => return Err(JumanppWorkerError::TimedOut)
+            }
+        }
+    }
+
+    // Whatever jumanpp last wrote to stderr.
+    fn stderr(&mut self) -> String {
+        // Give it a moment to exit so that the stderr thread has a chance to read everything.
+        let exit_deadline = Instant::now() + Duration::from_millis(500);
+        while matches!(self.child.try_wait(), Ok(None)) && Instant::now() < exit_deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        self.stderr.lock().unwrap_or_else(|e| e.into_inner()).trim().to_string()
+    }
+}
+
+impl Drop for JumanppWorker {
+    fn drop(&mut self) {
+        // Make sure we don't leave stray jumanpp processes lying around.
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+enum JumanppWorkerError {
+    Crashed,
+    TimedOut,
+    InvalidOutput(String)
+}
+
+// Tokenizes by streaming sentences through a small pool of long-lived jumanpp processes.
+pub struct JumanppTokenizer {
+    workers: Vec<Mutex<Option<JumanppWorker>>>,
+    next_worker: AtomicUsize,
+    timeout: Duration,
+    version: String
+}
+
+impl JumanppTokenizer {
+    pub fn new(worker_count: usize, timeout: Duration) -> Self {
+        Self {
+            workers: (0..worker_count.max(1)).map(|_| Mutex::new(None)).collect(),
+            next_worker: AtomicUsize::new(0),
+            timeout,
+            version: Self::installed_version()
+        }
+    }
+
+    // Ask jumanpp which version it is, e.g. "Juman++ Version: 2.0.0-rc4"
+    // If it isn't installed we'll find out properly when we try to tokenize something.
+    fn installed_version() -> String {
+        Command::new("jumanpp")
+            .arg("--version")
+            .output()
+            .ok()
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .and_then(|output| output.lines().next().map(|line| line.trim().to_string()))
+            .map(|line| line.rsplit(' ').next().unwrap_or(&line).to_string())
+            .filter(|version| !version.is_empty())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    // Run a sentence through one of the workers, (re)starting it if it isn't running.
+    fn analyze(&self, sentence: &str) -> KnowledgeResult<Vec<String>> {
+        // Hand out workers round robin, only blocking if the one we've been given is busy.
+        let index = self.next_worker.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        let mut worker_slot = self.workers[index].lock().unwrap_or_else(|e| e.into_inner());
+
+        // If jumanpp has crashed, restart it and give the sentence one more go.
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+
+            let worker = match worker_slot.as_mut() {
+                Some(worker) => worker,
+                None => worker_slot.insert(JumanppWorker::spawn()?)
+            };
+
+            let error = match self.analyze_with_worker(worker, sentence) {
+                Ok(output) => return Ok(output),
+                Err(JumanppWorkerError::Crashed) => {
+                    let stderr = worker.stderr();
+                    log::error!("jumanpp worker crashed while tokenizing: {}\n{}", sentence, stderr);
+
+                    if attempts < 2 {
+                        *worker_slot = None;
+                        continue;
+                    }
+                    KnowledgeError::TokenizerCrashed(stderr)
+                },
+                Err(JumanppWorkerError::TimedOut) => {
+                    // Don't retry, chances are the sentence itself is the problem.
+                    log::error!("jumanpp timed out after {:?} tokenizing: {}", self.timeout, sentence);
+                    KnowledgeError::TokenizerTimedOut(self.timeout)
+                },
+                Err(JumanppWorkerError::InvalidOutput(output)) => {
+                    log::error!("jumanpp gave invalid output tokenizing: {}", sentence);
+                    KnowledgeError::TokenizerInvalidOutput(output)
+                }
+            };
+
+            // Whatever went wrong, the worker can't be trusted to be in a sensible state any more.
+            *worker_slot = None;
+            return Err(error);
+        }
+    }
+
+    fn analyze_with_worker(&self, worker: &mut JumanppWorker, sentence: &str) -> Result<Vec<String>, JumanppWorkerError> {
+        // jumanpp treats every line of input as a separate sentence, so feed it line by line
+        // which gives the same results as piping the whole thing in at once.
+        let mut output = Vec::new();
+        for line in sentence.lines().filter(|line| !line.trim().is_empty()) {
+            output.extend(worker.analyze_line(line, self.timeout)?);
+        }
+
+        Ok(output)
+    }
+}
+
+impl Tokenizer for JumanppTokenizer {
+    fn tokenize(&self, sentence: &str) -> KnowledgeResult<Vec<Morpheme>> {
+        let output = self.analyze(sentence)?;
+        let mut morphemes = Vec::new();
+
+        // Each line of the output is a morpheme (in order).
+        for line in output {
+            // Ignore lines that start with '@', these specify aliases (for words that have exactly the same spelling.)
+            // For now we'll just ignore these, there's no way for us to know which alias is the 'correct' one so just pick
+            // the first one (most common).
+            if line.starts_with("@ ") {
+                continue;
+            }
+
+            // A line we can't make sense of is only one morpheme, so skip it rather than losing the whole sentence.
+            // Output that's broken as a whole (e.g. no EOS) is caught by the worker.
+            let Some(morpheme) = parse_jumanpp_line(&line) else {
+                log::warn!("Skipping jumanpp output we couldn't parse: {}", line);
+                continue;
+            };
+
+            // Spaces come through as morphemes too, we don't want to include these.
+            if !morpheme.surface.trim().is_empty() {
+                morphemes.push(morpheme);
+            }
+        }
+
+        assign_offsets(sentence, &mut morphemes);
+        Ok(morphemes)
+    }
+
+    fn name(&self) -> &str {
+        "jumanpp"
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+}
+
+// Parse a single line of jumanpp output.
+// https://github.com/ku-nlp/jumanpp/blob/master/docs/output.md
+// A line is made up of 11 space separated fields followed by the semantic info:
+// surface reading dictionary_form pos pos_id sub_pos sub_pos_id conj_type conj_type_id conj_form conj_form_id semantic_info
+// The semantic info is either NIL or a quoted string that can contain spaces.
+// Spaces within a field are written as '\␣' so splitting the first 11 fields on spaces is safe.
+fn parse_jumanpp_line(line: &str) -> Option<Morpheme> {
+    let mut fields = line.splitn(12, ' ');
+    let mut next_field = || fields.next().map(unescape_jumanpp_field);
+
+    let surface = next_field()?;
+    let reading = next_field()?;
+    let dictionary_form = next_field()?;
+    let pos = next_field()?;
+    next_field()?; // pos id
+    let sub_pos = next_field()?;
+    next_field()?; // sub pos id
+    let conjugation_type = next_field()?;
+    next_field()?; // conjugation type id
+    let conjugation_form = next_field()?;
+    next_field()?; // conjugation form id
+
+    let semantic_info = match fields.next()? {
+        "NIL" => String::new(),
+        quoted => quoted
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .unwrap_or(quoted)
+            .to_string()
+    };
+
+    Some(Morpheme {
+        surface,
+        reading,
+        dictionary_form,
+        pos,
+        sub_pos,
+        conjugation_type,
+        conjugation_form,
+        semantic_info,
+        synthetic_representative: false,
+        offset: 0
+    })
+}
+
+// Okay, so for some reason '\␣' is used to refer to a space.
+fn unescape_jumanpp_field(field: &str) -> String {
+    field.replace(r"\␣", " ")
+}
+
+// The version of lindera in Cargo.toml and the dictionary it's built with, which is compiled in and so changes along with the crate.
+// Sentences are retokenized when this changes, so it has to be bumped whenever the lindera dependency is.
+const LINDERA_VERSION: &str = "0.14.0-ipadic";
+
+// Tokenizes in-process with lindera and the IPADIC dictionary that's compiled into the binary.
+pub struct LinderaTokenizer {
+    tokenizer: lindera::tokenizer::Tokenizer
+}
+
+impl LinderaTokenizer {
+    pub fn new() -> KnowledgeResult<Self> {
+        let tokenizer = lindera::tokenizer::Tokenizer::new()
+            .map_err(|e| KnowledgeError::TokenizerError(format!("Couldn't create lindera tokenizer: {}", e)))?;
+
+        Ok(Self {
+            tokenizer
+        })
+    }
+}
+
+impl Tokenizer for LinderaTokenizer {
+    fn tokenize(&self, sentence: &str) -> KnowledgeResult<Vec<Morpheme>> {
+        let tokens = self.tokenizer.tokenize(sentence)
+            .map_err(|e| KnowledgeError::TokenizerError(format!("lindera couldn't tokenize the sentence: {}", e)))?;
+
+        let mut morphemes = Vec::new();
+        for token in tokens {
+            // Lindera gives back whitespace as tokens, skip those.
+            if token.text.trim().is_empty() {
+                continue;
+            }
+
+            // The IPADIC details are:
+            // 品詞, 品詞細分類1, 品詞細分類2, 品詞細分類3, 活用型, 活用形, 原形, 読み, 発音
+            // Unknown words only have a single "UNK" entry and '*' is used for fields that don't apply,
+            // just use the text as it appears where there's nothing better.
+            let detail = |index: usize| match token.detail.get(index) {
+                Some(value) if value != "*" => Some(value.clone()),
+                _ => None
+            };
+
+            morphemes.push(Morpheme {
+                surface: token.text.to_string(),
+                reading: detail(7).unwrap_or_else(|| token.text.to_string()),
+                dictionary_form: detail(6).unwrap_or_else(|| token.text.to_string()),
+                pos: detail(0).unwrap_or_default(),
+                sub_pos: detail(1).unwrap_or_default(),
+                conjugation_type: detail(4).unwrap_or_default(),
+                conjugation_form: detail(5).unwrap_or_default(),
+                semantic_info: String::new(),
+                synthetic_representative: false,
+                offset: 0
+            });
+        }
+
+        assign_offsets(sentence, &mut morphemes);
+        Ok(morphemes)
+    }
+
+    fn name(&self) -> &str {
+        "lindera"
+    }
+
+    fn version(&self) -> &str {
+        LINDERA_VERSION
+    }
+}
+
+// Splits sentences up on Unicode word boundaries, for languages that put spaces between their words.
+// There's no dictionary behind it so words are only lowercased, not de-conjugated.
+pub struct WordBoundaryTokenizer;
+
+impl Tokenizer for WordBoundaryTokenizer {
+    fn tokenize(&self, sentence: &str) -> KnowledgeResult<Vec<Morpheme>> {
+        let mut morphemes = Vec::new();
+        let mut char_offset = 0;
+        for segment in sentence.split_word_bounds() {
+            let offset = char_offset;
+            char_offset += segment.chars().count();
+
+            if segment.trim().is_empty() {
+                continue;
+            }
+
+            // Anything without a letter in it is either a number (3.5, 1,000) or punctuation.
+            let (pos, sub_pos) = match (segment.chars().any(char::is_alphabetic), segment.chars().any(char::is_numeric)) {
+                (true, _) => ("", ""),
+                (false, true) => PartOfSpeech::Numeral.tags(),
+                (false, false) => PartOfSpeech::Symbol.tags()
+            };
+
+            let lowercase = segment.to_lowercase();
+            morphemes.push(Morpheme {
+                surface: segment.to_string(),
+                reading: lowercase.clone(),
+                dictionary_form: lowercase,
+                pos: pos.to_string(),
+                sub_pos: sub_pos.to_string(),
+                conjugation_type: String::new(),
+                conjugation_form: String::new(),
+                semantic_info: String::new(),
+                synthetic_representative: false,
+                offset
+            });
+        }
+
+        Ok(morphemes)
+    }
+
+    fn name(&self) -> &str {
+        "word_boundary"
+    }
+
+    fn version(&self) -> &str {
+        "1"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_jumanpp_line() {
+        let morpheme = parse_jumanpp_line(r#"食べた たべた 食べる 動詞 2 * 0 母音動詞 1 タ形 10 "代表表記:食べる/たべる""#).unwrap();
+
+        assert_eq!(morpheme.surface, "食べた");
+        assert_eq!(morpheme.reading, "たべた");
+        assert_eq!(morpheme.dictionary_form, "食べる");
+        assert_eq!(morpheme.pos, "動詞");
+        assert_eq!(morpheme.sub_pos, "*");
+        assert_eq!(morpheme.conjugation_type, "母音動詞");
+        assert_eq!(morpheme.conjugation_form, "タ形");
+        assert_eq!(morpheme.semantic_info, "代表表記:食べる/たべる");
+        assert!(!morpheme.synthetic_representative);
+    }
+
+    #[test]
+    fn keeps_spaces_in_the_semantic_info() {
+        let morpheme = parse_jumanpp_line(r#"人参 にんじん 人参 名詞 6 普通名詞 1 * 0 * 0 "代表表記:人参/にんじん カテゴリ:植物;人工物-食べ物""#).unwrap();
+
+        assert_eq!(morpheme.semantic_info, "代表表記:人参/にんじん カテゴリ:植物;人工物-食べ物");
+    }
+
+    #[test]
+    fn nil_semantic_info_is_empty() {
+        let morpheme = parse_jumanpp_line("。 。 。 特殊 1 句点 1 * 0 * 0 NIL").unwrap();
+
+        assert_eq!(morpheme.surface, "。");
+        assert_eq!(morpheme.semantic_info, "");
+    }
+
+    #[test]
+    fn unescapes_spaces() {
+        let morpheme = parse_jumanpp_line(r"\␣ \␣ \␣ 特殊 1 空白 6 * 0 * 0 NIL").unwrap();
+
+        assert_eq!(morpheme.surface, " ");
+        assert_eq!(morpheme.reading, " ");
+        assert_eq!(morpheme.sub_pos, "空白");
+    }
+
+    #[test]
+    fn rejects_lines_that_are_missing_fields() {
+        assert_eq!(parse_jumanpp_line(""), None);
+        assert_eq!(parse_jumanpp_line("EOS"), None);
+        assert_eq!(parse_jumanpp_line("食べた たべた 食べる 動詞 2 * 0 母音動詞 1 タ形 10"), None);
+    }
+
+    #[test]
+    fn unquoted_semantic_info_is_kept_as_is() {
+        let morpheme = parse_jumanpp_line("猫 ねこ 猫 名詞 6 普通名詞 1 * 0 * 0 代表表記:猫/ねこ").unwrap();
+
+        assert_eq!(morpheme.semantic_info, "代表表記:猫/ねこ");
+    }
+}
@@ -0,0 +1,152 @@
+use crate::tokenizer::{Morpheme, PartOfSpeech, to_hiragana};
+
+// A word the tokenizer keeps getting wrong, like a character's name.
+// Wherever the pattern appears in a sentence it's cut up exactly at the '|'s, so "ルークスカイウォーカー"
+// forces the tokenizer's pieces to be merged into one word and "ルーク|スカイウォーカー" forces a split.
+#[derive(Clone, Debug)]
+pub struct UserDictionaryEntry {
+    pub pattern: String,
+    // The reading of each piece, split up with '|'s in the same way. Pieces without one are read as written.
+    pub reading: Option<String>,
+    pub pos: PartOfSpeech
+}
+
+impl UserDictionaryEntry {
+    // Parse a line of a user dictionary file: the pattern, then optionally a reading and a part of speech, separated by tabs.
+    // e.g. "ルーク|スカイウォーカー	るーく|すかいうぉーかー	noun"
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t').map(str::trim);
+        let pattern = fields.next().filter(|pattern| !pattern.replace('|', "").is_empty())?;
+        let reading = fields.next().filter(|reading| !reading.is_empty());
+        let pos = match fields.next().filter(|pos| !pos.is_empty()) {
+            Some(name) => PartOfSpeech::from_name(name)?,
+            None => PartOfSpeech::Noun
+        };
+
+        Some(Self {
+            pattern: pattern.to_string(),
+            reading: reading.map(str::to_string),
+            pos
+        })
+    }
+
+    // The text the entry matches in a sentence.
+    pub fn text(&self) -> String {
+        self.pattern.replace('|', "")
+    }
+
+    // The morphemes the entry becomes when it's found at the given offset.
+    fn morphemes(&self, offset: usize) -> Vec<Morpheme> {
+        let pieces: Vec<&str> = self.pattern.split('|').filter(|piece| !piece.is_empty()).collect();
+        let readings: Vec<&str> = self.reading.as_deref()
+            .map(|reading| reading.split('|').filter(|piece| !piece.is_empty()).collect())
+            .unwrap_or_default();
+
+        let mut offset = offset;
+        pieces.iter().enumerate().map(|(i, piece)| {
+            // If the readings don't line up with the pieces there's no telling which goes with which.
+            let reading = match readings.len() == pieces.len() {
+                true => to_hiragana(readings[i]),
+                false => to_hiragana(piece)
+            };
+            let morpheme = Morpheme::user_defined(piece, piece, &reading, self.pos, offset);
+            offset += piece.chars().count();
+            morpheme
+        }).collect()
+    }
+}
+
+// Forced segmentations applied on top of whatever the tokenizer comes up with.
+#[derive(Default)]
+pub struct UserDictionary {
+    entries: Vec<UserDictionaryEntry>
+}
+
+impl UserDictionary {
+    pub fn new(mut entries: Vec<UserDictionaryEntry>) -> Self {
+        // Longer entries are matched first so that they win over any entries inside them.
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.text().chars().count()));
+        Self { entries }
+    }
+
+    // Re-cut the tokenizer's morphemes wherever an entry appears in the sentence.
+    // Any tokenizer morphemes that straddle the edge of an entry are trimmed down to the part outside it.
+    pub fn apply(&self, sentence: &str, morphemes: Vec<Morpheme>) -> Vec<Morpheme> {
+        let chars: Vec<char> = sentence.chars().collect();
+
+        let mut matches: Vec<(usize, usize, &UserDictionaryEntry)> = Vec::new();
+        for entry in &self.entries {
+            let text: Vec<char> = entry.text().chars().collect();
+            if text.is_empty() {
+                continue;
+            }
+
+            let mut start = 0;
+            while start + text.len() <= chars.len() {
+                let end = start + text.len();
+                if chars[start..end] == text[..] && !matches.iter().any(|(s, e, _)| start < *e && *s < end) {
+                    matches.push((start, end, entry));
+                    start = end;
+                } else {
+                    start += 1;
+                }
+            }
+        }
+
+        if matches.is_empty() {
+            return morphemes;
+        }
+
+        let mut result = Vec::new();
+        for morpheme in morphemes {
+            let start = morpheme.offset;
+            let end = start + morpheme.surface.chars().count();
+
+            let mut overlapping: Vec<(usize, usize)> = matches.iter()
+                .filter(|(s, e, _)| *s < end && start < *e)
+                .map(|(s, e, _)| (*s, *e))
+                .collect();
+            if overlapping.is_empty() {
+                result.push(morpheme);
+                continue;
+            }
+            overlapping.sort();
+
+            let mut cursor = start;
+            for (s, e) in overlapping {
+                if s > cursor {
+                    result.push(fragment(&morpheme, &chars, cursor, s));
+                }
+                cursor = cursor.max(e);
+            }
+            if cursor < end {
+                result.push(fragment(&morpheme, &chars, cursor, end));
+            }
+        }
+
+        for (start, _, entry) in matches {
+            result.extend(entry.morphemes(start));
+        }
+        result.sort_by_key(|morpheme| morpheme.offset);
+
+        result
+    }
+}
+
+// Whatever is left of a tokenizer morpheme after an entry has taken part of it.
+// We don't know how the leftover is read or what its dictionary form is, so it's taken as written.
+fn fragment(morpheme: &Morpheme, chars: &[char], start: usize, end: usize) -> Morpheme {
+    let surface: String = chars[start.min(chars.len())..end.min(chars.len())].iter().collect();
+    Morpheme {
+        reading: to_hiragana(&surface),
+        dictionary_form: surface.clone(),
+        surface,
+        pos: morpheme.pos.clone(),
+        sub_pos: morpheme.sub_pos.clone(),
+        conjugation_type: "*".to_string(),
+        conjugation_form: "*".to_string(),
+        semantic_info: String::new(),
+        synthetic_representative: false,
+        offset: start
+    }
+}